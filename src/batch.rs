@@ -0,0 +1,306 @@
+//! Batch mode of `glz c` and `glz d`, turning every file given, or found in the
+//! directories given, into a file next to it.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use glz::GlzError;
+
+/// Extension of compressed files
+const EXTENSION: &str = "glz";
+
+/// The paths `c` and `d` were given
+pub enum Paths
+{
+    /// One input into one output, `-` being stdin or stdout
+    Single(String, String),
+    /// Files and directories, each file turned into one next to it
+    Batch(Vec<PathBuf>)
+}
+
+/// Take the paths of `c` and `d` from what is left of the arguments.
+///
+/// `-c` takes one input, written to stdout, `-o` one input written to `output`,
+/// and a lone `-` is stdin into stdout.
+/// Two paths are an input and its output, as before batches, unless `recursive` is set,
+/// either is a directory, or both are existing files `output_name` names outputs for.
+/// So `glz d *.glz` matching two files decompresses both, while `glz c data data.glz`
+/// and `glz d data.glz data` still replace their output. Anything else is a batch
+pub fn take_paths(
+    pargs: pico_args::Arguments, stdout: bool, recursive: bool, output: Option<String>,
+    output_name: impl Fn(&Path) -> Result<PathBuf, &'static str>
+) -> Paths
+{
+    let paths: Vec<OsString> = pargs.finish();
+    let is_dir = |path: &OsString| Path::new(path).is_dir();
+    // what a shell glob such as *.glz expands to
+    let is_batch_input =
+        |path: &OsString| Path::new(path).is_file() && output_name(Path::new(path)).is_ok();
+    let is_batch = |input: &OsString, output: &OsString| {
+        recursive
+            || is_dir(input)
+            || is_dir(output)
+            || (is_batch_input(input) && is_batch_input(output))
+    };
+
+    let single = match (paths.as_slice(), stdout, output.as_deref())
+    {
+        (_, true, Some(_)) => crate::exit_with("-c and -o can not be given together"),
+        ([input], false, Some(output)) => Some((input, OsStr::new(output))),
+        (_, false, Some(_)) => crate::exit_with("-o takes exactly one input file"),
+        ([input], true, None) => Some((input, OsStr::new("-"))),
+        (_, true, None) => crate::exit_with("-c takes exactly one input file"),
+        ([input], false, None) if input == "-" => Some((input, OsStr::new("-"))),
+        ([input, output], false, None) if !is_batch(input, output) =>
+        {
+            Some((input, output.as_os_str()))
+        }
+        _ => None
+    };
+    if let Some((input, output)) = single
+    {
+        let utf8 = |path: &OsStr| {
+            path.to_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| crate::exit_with(format!("{path:?} is not valid UTF-8")))
+        };
+        return Paths::Single(utf8(input), utf8(output));
+    }
+    if paths.is_empty()
+    {
+        crate::exit_with("No input files given");
+    }
+    if paths.iter().any(|path| path == "-")
+    {
+        crate::exit_with("- can only be compressed or decompressed on its own, or with -c");
+    }
+    Paths::Batch(paths.into_iter().map(PathBuf::from).collect())
+}
+
+/// Name of the compressed file next to `path`, or why it has none
+pub fn compressed_name(path: &Path) -> Result<PathBuf, &'static str>
+{
+    if path.extension() == Some(OsStr::new(EXTENSION))
+    {
+        return Err("already compressed");
+    }
+    let mut name = path.as_os_str().to_owned();
+
+    name.push(".");
+    name.push(EXTENSION);
+    Ok(PathBuf::from(name))
+}
+
+/// Name of the decompressed file next to `path`, or why it has none
+pub fn decompressed_name(path: &Path) -> Result<PathBuf, &'static str>
+{
+    if path.extension() != Some(OsStr::new(EXTENSION))
+    {
+        return Err("not a .glz file");
+    }
+    Ok(path.with_extension(""))
+}
+
+/// The files to turn into others: `paths` that are files, and the files in
+/// `paths` that are directories for which `output_name` names an output,
+/// in their subdirectories too if `recursive`.
+///
+/// Directories that can not be read are reported and set `failed`
+fn collect_files(
+    paths: &[PathBuf], recursive: bool,
+    output_name: &impl Fn(&Path) -> Result<PathBuf, &'static str>, failed: &mut bool
+) -> Vec<PathBuf>
+{
+    let mut files = Vec::new();
+
+    for path in paths
+    {
+        if path.is_dir()
+        {
+            walk(path, recursive, output_name, &mut files).unwrap_or_else(|e| {
+                eprintln!("{}: {e}", path.display());
+                *failed = true;
+            });
+        }
+        else
+        {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+fn walk(
+    dir: &Path, recursive: bool,
+    output_name: &impl Fn(&Path) -> Result<PathBuf, &'static str>, files: &mut Vec<PathBuf>
+) -> io::Result<()>
+{
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    // the same order on every system
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries
+    {
+        let path = entry.path();
+
+        // links to directories are not followed, they may loop
+        if entry.file_type()?.is_dir()
+        {
+            if recursive
+            {
+                walk(&path, recursive, output_name, files)?;
+            }
+        }
+        else if path.is_file() && output_name(&path).is_ok()
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Create the output file `path`, failing with `AlreadyExists` if it exists
+/// and `force` is not set
+fn create_output(path: &Path, force: bool) -> io::Result<File>
+{
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(path)
+}
+
+/// Turn every file of `paths` into the file named by `output_name` next to it with `process`,
+/// which returns the number of bytes it read and wrote. `verb` says what it did.
+///
+/// Existing outputs are only replaced if `force` is set, and outputs are removed again
+/// if `process` fails. Returns whether every file was processed
+pub fn run_batch(
+    paths: &[PathBuf], recursive: bool, force: bool, verb: &str,
+    output_name: impl Fn(&Path) -> Result<PathBuf, &'static str>,
+    mut process: impl FnMut(&Path, File) -> Result<(u64, u64), GlzError>
+) -> bool
+{
+    let start = Instant::now();
+    let mut failed = false;
+    let files = collect_files(paths, recursive, &output_name, &mut failed);
+
+    let mut processed = 0;
+    let mut total_read = 0;
+    let mut total_written = 0;
+
+    for input in &files
+    {
+        let output_path = match output_name(input)
+        {
+            Ok(output_path) => output_path,
+            Err(reason) =>
+            {
+                eprintln!("{}: {reason}, skipped", input.display());
+                failed = true;
+                continue;
+            }
+        };
+        let output = match create_output(&output_path, force)
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists =>
+            {
+                eprintln!("{}: already exists, -f replaces it", output_path.display());
+                failed = true;
+                continue;
+            }
+            Err(e) =>
+            {
+                eprintln!("{}: {e}", output_path.display());
+                failed = true;
+                continue;
+            }
+        };
+        match process(input, output)
+        {
+            Ok((read, written)) =>
+            {
+                eprintln!("{}: {read} -> {written}", input.display());
+                processed += 1;
+                total_read += read;
+                total_written += written;
+            }
+            Err(e) =>
+            {
+                eprintln!("{}: {e}", input.display());
+                // no half written outputs
+                let _ = fs::remove_file(&output_path);
+                failed = true;
+            }
+        }
+    }
+    eprintln!(
+        "{verb} {processed} files, {total_read} to {total_written} in {:?}",
+        start.elapsed()
+    );
+    !failed
+}
+
+#[test]
+fn batch_names_round_trip()
+{
+    let name = compressed_name(Path::new("dir/notes.txt")).unwrap();
+
+    assert_eq!(name, Path::new("dir/notes.txt.glz"));
+    assert_eq!(decompressed_name(&name).unwrap(), Path::new("dir/notes.txt"));
+
+    // compressed files are not compressed again, and others not decompressed
+    assert!(compressed_name(Path::new("notes.glz")).is_err());
+    assert!(decompressed_name(Path::new("notes.txt")).is_err());
+    assert!(decompressed_name(Path::new(".glz")).is_err());
+}
+
+#[test]
+fn two_files_are_a_batch_only_if_both_have_outputs()
+{
+    let dir = std::env::temp_dir().join(format!("glz-batch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let file = |name: &str| {
+        let path = dir.join(name);
+        fs::write(&path, name).unwrap();
+        path.into_os_string()
+    };
+    let (a_txt, b_txt, a_glz, b_glz) = (file("a.txt"), file("b.txt"), file("a.glz"), file("b.glz"));
+    let missing = dir.join("new.bin").into_os_string();
+
+    let take = |paths: &[&OsString], output_name: fn(&Path) -> _| {
+        let args = pico_args::Arguments::from_vec(paths.iter().map(|&p| p.clone()).collect());
+        take_paths(args, false, false, None, output_name)
+    };
+    let is_batch = |paths: Paths| matches!(paths, Paths::Batch(paths) if paths.len() == 2);
+
+    // `glz c *.txt` and `glz d *.glz` matching two files
+    assert!(is_batch(take(&[&a_txt, &b_txt], compressed_name)));
+    assert!(is_batch(take(&[&a_glz, &b_glz], decompressed_name)));
+
+    // an input and its output, replaced as before batches
+    assert!(!is_batch(take(&[&a_txt, &a_glz], compressed_name)));
+    assert!(!is_batch(take(&[&a_glz, &a_txt], decompressed_name)));
+    assert!(!is_batch(take(&[&a_txt, &missing], compressed_name)));
+
+    // -o always names the output
+    let args = pico_args::Arguments::from_vec(vec![a_txt.clone()]);
+    let output = Some(b_txt.to_str().unwrap().to_string());
+    assert!(matches!(
+        take_paths(args, false, false, output, compressed_name),
+        Paths::Single(input, output) if input == a_txt.to_str().unwrap() && output == b_txt.to_str().unwrap()
+    ));
+
+    // outputs of a batch are only replaced with -f
+    let err = create_output(Path::new(&a_glz), false).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert!(create_output(Path::new(&a_glz), true).is_ok());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,176 @@
+//! Checksums used by the format.
+
+const PRIME32_1: u32 = 0x9E37_79B1;
+const PRIME32_2: u32 = 0x85EB_CA77;
+const PRIME32_3: u32 = 0xC2B2_AE3D;
+const PRIME32_4: u32 = 0x27D4_EB2F;
+const PRIME32_5: u32 = 0x1656_67B1;
+
+#[inline(always)]
+fn read_u32(bytes: &[u8]) -> u32
+{
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+#[inline(always)]
+fn round(accumulator: u32, lane: u32) -> u32
+{
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME32_2))
+        .rotate_left(13)
+        .wrapping_mul(PRIME32_1)
+}
+
+/// Streaming xxHash32, for data that arrives in pieces
+#[derive(Clone)]
+pub struct Xxh32
+{
+    seed:     u32,
+    lanes:    [u32; 4],
+    // bytes not yet making up a whole stripe
+    buffer:   [u8; 16],
+    buffered: usize,
+    length:   u64
+}
+
+impl Xxh32
+{
+    pub fn new(seed: u32) -> Xxh32
+    {
+        Xxh32 {
+            seed,
+            lanes: [
+                seed.wrapping_add(PRIME32_1).wrapping_add(PRIME32_2),
+                seed.wrapping_add(PRIME32_2),
+                seed,
+                seed.wrapping_sub(PRIME32_1)
+            ],
+            buffer: [0; 16],
+            buffered: 0,
+            length: 0
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8])
+    {
+        self.length += data.len() as u64;
+
+        if self.buffered > 0
+        {
+            let length = data.len().min(16 - self.buffered);
+
+            self.buffer[self.buffered..self.buffered + length].copy_from_slice(&data[..length]);
+            self.buffered += length;
+            data = &data[length..];
+
+            if self.buffered < 16
+            {
+                return;
+            }
+            let stripe = self.buffer;
+            self.stripe(&stripe);
+            self.buffered = 0;
+        }
+        let mut stripes = data.chunks_exact(16);
+
+        for stripe in &mut stripes
+        {
+            self.stripe(stripe);
+        }
+        let tail = stripes.remainder();
+
+        self.buffer[..tail.len()].copy_from_slice(tail);
+        self.buffered = tail.len();
+    }
+
+    #[inline(always)]
+    fn stripe(&mut self, stripe: &[u8])
+    {
+        for (i, lane) in self.lanes.iter_mut().enumerate()
+        {
+            *lane = round(*lane, read_u32(&stripe[4 * i..]));
+        }
+    }
+
+    /// The hash of the data so far
+    pub fn finish(&self) -> u32
+    {
+        let mut hash = if self.length >= 16
+        {
+            let v = self.lanes;
+
+            v[0].rotate_left(1)
+                .wrapping_add(v[1].rotate_left(7))
+                .wrapping_add(v[2].rotate_left(12))
+                .wrapping_add(v[3].rotate_left(18))
+        }
+        else
+        {
+            self.seed.wrapping_add(PRIME32_5)
+        };
+        hash = hash.wrapping_add(self.length as u32);
+
+        let mut tail = &self.buffer[..self.buffered];
+
+        while tail.len() >= 4
+        {
+            hash = hash
+                .wrapping_add(read_u32(tail).wrapping_mul(PRIME32_3))
+                .rotate_left(17)
+                .wrapping_mul(PRIME32_4);
+            tail = &tail[4..];
+        }
+        for byte in tail
+        {
+            hash = hash
+                .wrapping_add(u32::from(*byte).wrapping_mul(PRIME32_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME32_1);
+        }
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME32_2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME32_3);
+        hash ^= hash >> 16;
+
+        hash
+    }
+}
+
+/// xxHash32 of `data` with `seed`
+pub fn xxh32(data: &[u8], seed: u32) -> u32
+{
+    let mut hasher = Xxh32::new(seed);
+
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// ID of a preset dictionary, stored in the streams compressed with it.
+///
+/// See [`compress_with_dict`](crate::compress::compress_with_dict)
+pub fn dictionary_id(dictionary: &[u8]) -> u32
+{
+    xxh32(dictionary, 0)
+}
+
+#[test]
+fn xxh32_known_values()
+{
+    assert_eq!(xxh32(b"", 0), 0x02CC_5D05);
+    assert_eq!(xxh32(b"abc", 0), 0x32D1_53FF);
+    assert_eq!(
+        xxh32(b"Nobody inspects the spammish repetition", 0),
+        0xE229_3B2F
+    );
+
+    // the same hash whichever way the data is split
+    let data: Vec<u8> = (0..1000_u32).map(|i| (i * 7 + i / 13) as u8).collect();
+    let mut hasher = Xxh32::new(5);
+
+    for piece in data.chunks(37)
+    {
+        hasher.update(piece);
+    }
+    assert_eq!(hasher.finish(), xxh32(&data, 5));
+}
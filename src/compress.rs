@@ -1,12 +1,39 @@
+use std::convert::Infallible;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
-use std::time::Instant;
 
-use crate::compress::hash_chains::{compress_block, HcMatchFinder};
-use crate::constants::{BLOCK_SIZE, DEPTH_STRIDE, GLZ_MIN_MATCH, MEM_SIZE, SLOP_BYTES};
+pub use crate::compress::binary_tree::BtMatchFinder;
+pub use crate::compress::hash_chains::{
+    compress_block, compress_block_with_history, HcMatchFinder
+};
+pub use crate::compress::levels::{
+    CompressionParams, MatchFinder, Strategy, DEFAULT_LEVEL, MAX_LEVEL, MIN_LEVEL
+};
+pub use crate::compress::trainer::train_dictionary;
+pub use crate::compress::writer::GlzWriter;
+use crate::checksum::{dictionary_id, xxh32, Xxh32};
+use crate::compress::parallel::BlockPool;
+use crate::constants::{BLOCK_SIZE, GLZ_MIN_MATCH, HISTORY_SIZE, SLOP_BYTES};
+use crate::errors::GlzError;
+use crate::frame::{write_index, FrameHeader, IndexEntry, END_MARK};
+use crate::utils::{count, v_hash, BlockPrefix};
 
+mod binary_tree;
 mod hash_chains;
+mod levels;
+mod optimal;
+mod parallel;
+mod trainer;
+mod writer;
+
+/// A match candidate reported by a match finder
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Match
+{
+    pub length: usize,
+    pub offset: usize
+}
 
 #[derive(Copy, Clone, Default, Debug)]
 pub struct EncodeSequence
@@ -18,58 +45,829 @@ pub struct EncodeSequence
     pub cost:  usize
 }
 
-pub fn compress(input_file: String, output_file: String)
+/// Options shared by the compressors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressOptions
+{
+    /// Compression level, from [`MIN_LEVEL`] (fastest) to [`MAX_LEVEL`] (strongest)
+    pub level:            u8,
+    /// Parser to use instead of the one picked by `level`
+    pub strategy:         Option<Strategy>,
+    /// Match finder search depth to use instead of the one picked by `level`
+    pub search_depth:     Option<i32>,
+    /// Let matches reach up to `HISTORY_SIZE` bytes back into earlier blocks.
+    ///
+    /// Improves the ratio, but blocks can then only be decoded in order
+    pub linked:           bool,
+    /// Number of blocks compressed at once, each on its own thread,
+    /// capped at the number of cores.
+    ///
+    /// The output is the same for any number of threads. Only used by
+    /// [`compress_to_vec_with`] and [`compress`], other compressors use one thread
+    pub threads:          usize,
+    /// Start the stream with a [`FrameHeader`] describing it.
+    ///
+    /// Every decompressor reads streams with and without one
+    pub header:           bool,
+    /// Follow every block with the xxHash32 of its uncompressed bytes,
+    /// so decoders can name a corrupt block.
+    ///
+    /// Implies `header`, which tells decoders the checksums are there
+    pub block_checksums:  bool,
+    /// End the stream with the xxHash32 of the whole input.
+    ///
+    /// Implies `header`, which tells decoders the checksum is there
+    pub content_checksum: bool,
+    /// End the stream with an index of its blocks, so a
+    /// [`SeekableReader`](crate::decompress::SeekableReader) can decode any part of it
+    /// without the blocks before.
+    ///
+    /// Implies `header`. The blocks are independent, `linked` is ignored, and streams
+    /// compressed with a dictionary are never seekable
+    pub seekable:         bool
+}
+
+impl CompressOptions
+{
+    /// Match finder and parser parameters for these options
+    pub fn params(&self) -> CompressionParams
+    {
+        let mut params = CompressionParams::from_level(self.level);
+
+        if let Some(strategy) = self.strategy
+        {
+            params.strategy = strategy;
+        }
+        if let Some(search_depth) = self.search_depth
+        {
+            params.search_depth = search_depth;
+        }
+        params
+    }
+
+    /// Whether blocks may reach into the blocks before them
+    pub(crate) fn links_blocks(&self) -> bool
+    {
+        self.linked && !self.seekable
+    }
+
+    pub(crate) fn match_finder(&self) -> HcMatchFinder
+    {
+        HcMatchFinder::with_params(BLOCK_SIZE, GLZ_MIN_MATCH, self.params())
+    }
+
+    /// The frame header to start a stream with, if these options ask for one
+    pub(crate) fn frame_header(
+        &self, dictionary_id: Option<u32>, content_size: Option<u64>
+    ) -> Option<FrameHeader>
+    {
+        let header =
+            self.header || self.block_checksums || self.content_checksum || self.seekable;
+
+        header.then_some(FrameHeader {
+            block_size: BLOCK_SIZE,
+            block_checksums: self.block_checksums,
+            content_checksum: self.content_checksum,
+            linked: self.links_blocks() || dictionary_id.is_some(),
+            dictionary_id,
+            content_size,
+            seekable: self.seekable && dictionary_id.is_none()
+        })
+    }
+}
+
+impl Default for CompressOptions
+{
+    fn default() -> Self
+    {
+        CompressOptions {
+            level:            DEFAULT_LEVEL,
+            strategy:         None,
+            search_depth:     None,
+            linked:           false,
+            threads:          1,
+            header:           false,
+            block_checksums:  false,
+            content_checksum: false,
+            seekable:         false
+        }
+    }
+}
+
+/// Input buffer of the compressors.
+///
+/// Holds the block being filled, followed by slack bytes for `compress_block`,
+/// and for linked streams up to `HISTORY_SIZE` bytes of the blocks before it
+/// or of the dictionary.
+pub(crate) struct BlockWindow
+{
+    buffer:    Vec<u8>,
+    // bytes of history before the block
+    history:   usize,
+    // bytes buffered in the block
+    length:    usize,
+    linked:    bool,
+    // follow every block with the checksum of its bytes
+    checksums: bool,
+    // hash table of looks_incompressible
+    probes:    Vec<u32>
+}
+
+impl BlockWindow
 {
-    let mut table = HcMatchFinder::new(BLOCK_SIZE, DEPTH_STRIDE as i32, GLZ_MIN_MATCH, 100);
+    pub fn new(options: &CompressOptions) -> BlockWindow
+    {
+        let history = if options.links_blocks() { HISTORY_SIZE } else { 0 };
+
+        BlockWindow {
+            buffer:    vec![0; history + BLOCK_SIZE + SLOP_BYTES],
+            history:   0,
+            length:    0,
+            linked:    options.links_blocks(),
+            checksums: options.block_checksums,
+            probes:    vec![0; 1 << PROBE_LOG]
+        }
+    }
+
+    /// A linked window whose first block reaches into the last
+    /// `HISTORY_SIZE` bytes of `dictionary`
+    pub fn with_dictionary(dictionary: &[u8], options: &CompressOptions) -> BlockWindow
+    {
+        let mut window = BlockWindow::new(&CompressOptions {
+            linked: true,
+            seekable: false,
+            ..*options
+        });
+        let keep = dictionary.len().min(HISTORY_SIZE);
+
+        window.buffer[..keep].copy_from_slice(&dictionary[dictionary.len() - keep..]);
+        window.history = keep;
+
+        window
+    }
 
-    let p = Path::new(&input_file);
+    /// Number of bytes buffered in the current block
+    pub fn len(&self) -> usize
+    {
+        self.length
+    }
+
+    /// Space left in the current block, call [`filled`](Self::filled)
+    /// after writing to it
+    pub fn spare(&mut self) -> &mut [u8]
+    {
+        &mut self.buffer[self.history + self.length..self.history + BLOCK_SIZE]
+    }
+
+    pub fn filled(&mut self, length: usize)
+    {
+        self.length += length;
+        debug_assert!(self.length <= BLOCK_SIZE);
+    }
+
+    /// Replace the window's contents with `block`, reaching back into `history`
+    pub fn load(&mut self, history: &[u8], block: &[u8])
+    {
+        debug_assert!(history.is_empty() || self.linked);
+
+        self.buffer[..history.len()].copy_from_slice(history);
+        self.history = history.len();
+        self.length = 0;
+        self.fill(block);
+    }
+
+    /// Copy as much of `data` as fits into the current block, returning the number of bytes copied
+    pub fn fill(&mut self, data: &[u8]) -> usize
+    {
+        let spare = self.spare();
+        let length = data.len().min(spare.len());
 
-    // allocate and add slack bytes, so that we don't panic in simd_decode
-    let mut max_in = Vec::with_capacity(MEM_SIZE + SLOP_BYTES);
-    max_in.resize(MEM_SIZE, 0);
+        spare[..length].copy_from_slice(&data[..length]);
+        self.filled(length);
 
-    let mut max_out = Vec::with_capacity(MEM_SIZE + SLOP_BYTES);
-    max_out.resize(MEM_SIZE, 0);
+        length
+    }
+
+    /// Compress the current block into `dest` with its length prefix and,
+    /// if asked for, its checksum, returning the number of bytes written.
+    ///
+    /// Blocks that [look incompressible](looks_incompressible) or do not shrink
+    /// are stored as they are.
+    ///
+    /// `dest` needs room for [`MAX_BLOCK_OUTPUT`] bytes
+    pub fn compress(&mut self, dest: &mut [u8], table: &mut HcMatchFinder) -> usize
+    {
+        let end = self.history + self.length;
+        let src = &self.buffer[..end];
+
+        let mut stored = looks_incompressible(src, self.history, &mut self.probes);
+        let mut size = 0;
+
+        if !stored
+        {
+            size = compress_block_with_history(src, self.history, &mut dest[4..], table);
+            table.reset();
+            stored = size >= self.length;
+        }
+        if stored
+        {
+            size = self.length;
+            dest[4..4 + size].copy_from_slice(&src[self.history..]);
+        }
+        let prefix = BlockPrefix {
+            size,
+            linked: self.history > 0 && !stored,
+            dictionary: false,
+            stored
+        };
+        dest[..4].copy_from_slice(&prefix.to_bytes());
+
+        let mut written = size + 4;
+
+        if self.checksums
+        {
+            let checksum = xxh32(&self.buffer[self.history..end], 0);
+
+            dest[written..written + 4].copy_from_slice(&checksum.to_le_bytes());
+            written += 4;
+        }
+
+        if self.linked
+        {
+            // the end of this block becomes the history of the next one
+            let keep = end.min(HISTORY_SIZE);
+
+            self.buffer.copy_within(end - keep..end, 0);
+            self.history = keep;
+        }
+        self.length = 0;
+
+        written
+    }
+}
+
+/// Size log2 of the hash table of [`looks_incompressible`]
+const PROBE_LOG: usize = 16;
+/// Shortest match [`looks_incompressible`] counts
+const PROBE_MATCH: usize = 6;
+/// Blocks shorter than this are quick to search and too short to guess about
+const PROBE_MIN_BLOCK: usize = 1 << 12;
+
+/// Guess whether the block `src[history..]` would not compress, from the bytes covered
+/// by the matches a single probe per position finds into the block or its history.
+///
+/// This is far cheaper than a match finder, so blocks of already compressed data,
+/// where less than 1/32 of the bytes match, skip the search and are stored.
+/// The few bytes a full search might save on such blocks are not worth its time.
+fn looks_incompressible(src: &[u8], history: usize, probes: &mut [u32]) -> bool
+{
+    if src.len() - history < PROBE_MIN_BLOCK
+    {
+        return false;
+    }
+    // positions are stored plus one, zero is an empty slot
+    probes.fill(0);
+
+    let mut matched = 0;
+    let mut position = 0;
+    // v_hash reads 8 bytes
+    let end = src.len().saturating_sub(8);
+
+    while position < end
+    {
+        // SAFETY: There are at least 8 bytes after position
+        let hash = unsafe { v_hash::<6>(src.as_ptr().add(position), PROBE_LOG) };
+        let candidate = probes[hash] as usize;
+
+        probes[hash] = position as u32 + 1;
+
+        if candidate > 0 && position >= history
+        {
+            let length = count(&src[candidate - 1..], &src[position..]);
+
+            if length >= PROBE_MATCH
+            {
+                matched += length;
+                position += length;
+                continue;
+            }
+        }
+        position += 1;
+    }
+    matched < (src.len() - history) / 32
+}
+
+/// Room [`BlockWindow::compress`] needs for a block, with its length prefix and checksum
+pub(crate) const MAX_BLOCK_OUTPUT: usize = max_compressed_size(BLOCK_SIZE) + 8;
+
+/// Largest size `compress_block` may write for an input of `size` bytes.
+///
+/// The worst case is a run of minimum length matches with far offsets,
+/// where each 3 byte match may cost a token plus a 4 byte offset. Literal copies
+/// also write up to 16 bytes past the end, hence the slop.
+pub const fn max_compressed_size(size: usize) -> usize
+{
+    2 * size + SLOP_BYTES
+}
+
+/// Compress `input` into a new vector using the default options.
+///
+/// The output is the same stream of length prefixed blocks
+/// the command line tool writes, and can be decoded by
+/// [`decompress_to_vec`](crate::decompress::decompress_to_vec).
+pub fn compress_to_vec(input: &[u8]) -> Vec<u8>
+{
+    compress_to_vec_with(input, &CompressOptions::default())
+}
+
+/// Compress `input` into a new vector using `options`.
+pub fn compress_to_vec_with(input: &[u8], options: &CompressOptions) -> Vec<u8>
+{
+    let mut pool = BlockPool::new(options);
+    let mut out = Vec::with_capacity(input.len() / 2 + 32);
+    let mut index = Vec::new();
+
+    let header = options.frame_header(None, Some(input.len() as u64));
+
+    if let Some(header) = header
+    {
+        out.extend_from_slice(&header.to_bytes());
+    }
+    // each batch reaches back into the input before it
+    for start in (0..input.len()).step_by(pool.batch_size())
+    {
+        let end = input.len().min(start + pool.batch_size());
+
+        let Ok(()) = pool.compress(&input[..end], start, |block, decompressed_size| {
+            index.push(IndexEntry {
+                compressed_offset: out.len() as u64,
+                decompressed_size: decompressed_size as u32
+            });
+            out.extend_from_slice(block);
+            Ok::<_, Infallible>(())
+        });
+    }
+    if let Some(header) = header
+    {
+        let checksum = header.content_checksum.then(|| xxh32(input, 0));
+        out.extend_from_slice(&end_of_stream(&header, checksum, &index));
+    }
+    out
+}
+
+/// Compress `input` into a new vector, letting matches reach into `dictionary`.
+///
+/// Small inputs that share content with the dictionary, such as messages of
+/// the same shape, compress far better than on their own. The [`dictionary_id`](crate::dictionary_id)
+/// is stored in the frame header, or without one in a record before the first block.
+/// The blocks are always linked, the first one reaching into the last `HISTORY_SIZE`
+/// bytes of the dictionary.
+///
+/// Decode it with [`decompress_with_dict`](crate::decompress::decompress_with_dict)
+/// and the same dictionary. Compression runs on the calling thread.
+pub fn compress_with_dict(input: &[u8], dictionary: &[u8], options: &CompressOptions) -> Vec<u8>
+{
+    let id = dictionary_id(dictionary);
+    let mut out = Vec::with_capacity(input.len() / 2 + 32);
 
-    // let mut temp = Vec::with_capacity(MEM_SIZE + SLOP_BYTES);
-    // temp.resize(MEM_SIZE, 0);
+    let header = options.frame_header(Some(id), Some(input.len() as u64));
 
-    let start = Instant::now();
-    let mut fd = File::open(p).unwrap();
-    let mut out_fd = OpenOptions::new()
+    if let Some(header) = header
+    {
+        out.extend_from_slice(&header.to_bytes());
+    }
+    else
+    {
+        let record = BlockPrefix {
+            size:       4,
+            linked:     false,
+            dictionary: true,
+            stored:     false
+        };
+        out.extend_from_slice(&record.to_bytes());
+        out.extend_from_slice(&id.to_le_bytes());
+    }
+
+    let mut table = options.match_finder();
+    let mut window = BlockWindow::with_dictionary(dictionary, options);
+    let mut scratch = vec![0; MAX_BLOCK_OUTPUT];
+
+    for block in input.chunks(BLOCK_SIZE)
+    {
+        window.fill(block);
+
+        let written = window.compress(&mut scratch, &mut table);
+        out.extend_from_slice(&scratch[..written]);
+    }
+    if let Some(header) = header
+    {
+        let checksum = header.content_checksum.then(|| xxh32(input, 0));
+        out.extend_from_slice(&end_of_stream(&header, checksum, &[]));
+    }
+    out
+}
+
+/// What follows the blocks of a stream with `header`: the end mark, the content
+/// `checksum` and the block `index`, those the header asks for
+pub(crate) fn end_of_stream(
+    header: &FrameHeader, checksum: Option<u32>, index: &[IndexEntry]
+) -> Vec<u8>
+{
+    let mut trailer = Vec::new();
+
+    if header.has_end_mark()
+    {
+        trailer.extend_from_slice(&END_MARK);
+    }
+    if let Some(checksum) = checksum
+    {
+        trailer.extend_from_slice(&checksum.to_le_bytes());
+    }
+    if header.seekable
+    {
+        write_index(index, &mut trailer);
+    }
+    trailer
+}
+
+/// Compress `input_file` into `output_file` with `options`,
+/// returning the number of bytes read and written.
+///
+/// See [`compress_file`]
+pub fn compress(
+    input_file: String, output_file: String, options: &CompressOptions
+) -> Result<(u64, u64), GlzError>
+{
+    let out_fd = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
         .open(&output_file)
-        .unwrap();
+        .map_err(|error| GlzError::Io {
+            block: 0,
+            offset: 0,
+            error
+        })?;
+
+    compress_file(input_file, out_fd, options)
+}
+
+/// Compress `input_file` into `output` with `options`,
+/// returning the number of bytes read and written.
+///
+/// The frame header records the content size if the input is a regular file.
+/// See [`Compressor`] for compressing many files.
+pub fn compress_file<W: Write>(
+    input_file: impl AsRef<Path>, output: W, options: &CompressOptions
+) -> Result<(u64, u64), GlzError>
+{
+    Compressor::new(options).compress_file(input_file, output)
+}
 
-    let mut total_bytes = 0;
-    let mut total_bytes_read = 0;
-    loop
+/// Compress everything read from `input` into `output` with `options`,
+/// returning the number of bytes read and written.
+///
+/// `input` is only read from, so it can be a pipe. If given, `content_size` is recorded
+/// in the frame header, and compression fails if the input turns out to be another size.
+/// See [`Compressor`] for compressing many streams.
+pub fn compress_stream<R: Read, W: Write>(
+    input: R, output: W, options: &CompressOptions, content_size: Option<u64>
+) -> Result<(u64, u64), GlzError>
+{
+    Compressor::new(options).compress_stream(input, output, content_size)
+}
+
+/// Compresses one stream after another with the same options.
+///
+/// The match finders and buffers, several megabytes per thread, are allocated by the
+/// first stream that needs them and reused for every stream after, so compressing many
+/// small files does not spend most of its time setting them up. The output is the same as from [`compress_stream`].
+///
+/// # Example
+/// ```no_run
+/// use glz::compress::{CompressOptions, Compressor};
+///
+/// let mut compressor = Compressor::new(&CompressOptions::default());
+///
+/// for name in ["a.txt", "b.txt"]
+/// {
+///     let output = std::fs::File::create(format!("{name}.glz")).unwrap();
+///     compressor.compress_file(name, output).unwrap();
+/// }
+/// ```
+pub struct Compressor
+{
+    options: CompressOptions,
+    pool:    BlockPool,
+    // a batch of blocks, preceded by the input before it for linked streams
+    buffer:  Vec<u8>
+}
+
+impl Compressor
+{
+    pub fn new(options: &CompressOptions) -> Compressor
+    {
+        Compressor {
+            options: *options,
+            pool:    BlockPool::new(options),
+            buffer:  Vec::new()
+        }
+    }
+
+    /// Options every stream is compressed with
+    pub fn options(&self) -> &CompressOptions
+    {
+        &self.options
+    }
+
+    /// Compress `input_file` into `output`, like [`compress_file`]
+    pub fn compress_file<W: Write>(
+        &mut self, input_file: impl AsRef<Path>, output: W
+    ) -> Result<(u64, u64), GlzError>
     {
-        let bytes_read = fd.read(&mut max_in[0..BLOCK_SIZE]).unwrap();
-        total_bytes_read += bytes_read;
+        let io_error = |error| GlzError::Io {
+            block: 0,
+            offset: 0,
+            error
+        };
+        let fd = File::open(input_file).map_err(io_error)?;
+
+        // the content size is only recorded for files, which have a known length
+        let metadata = fd.metadata().map_err(io_error)?;
+        let content_size = metadata.is_file().then_some(metadata.len());
+
+        self.compress_stream(fd, output, content_size)
+    }
+
+    /// Compress everything read from `input` into `output`, like [`compress_stream`]
+    pub fn compress_stream<R: Read, W: Write>(
+        &mut self, mut input: R, mut output: W, content_size: Option<u64>
+    ) -> Result<(u64, u64), GlzError>
+    {
+        let pool = &mut self.pool;
+        let buffer = &mut self.buffer;
+
+        let io_error = |block, offset| {
+            move |error| GlzError::Io {
+                block,
+                offset,
+                error
+            }
+        };
+
+        let history_size = if self.options.links_blocks() { HISTORY_SIZE } else { 0 };
+        let mut history = 0;
+
+        // batches of whole blocks, but no more of them than the content needs,
+        // so a small file does not get a buffer for every thread
+        let batch_size = content_size.map_or(pool.batch_size(), |size| {
+            let blocks = size.div_ceil(BLOCK_SIZE as u64).max(1);
+            (pool.batch_size() as u64).min(blocks * BLOCK_SIZE as u64) as usize
+        });
+        if buffer.len() < history_size + batch_size
+        {
+            buffer.resize(history_size + batch_size, 0);
+        }
+
+        let header = self.options.frame_header(None, content_size);
+        let mut total_bytes = 0;
+
+        if let Some(header) = header
+        {
+            let bytes = header.to_bytes();
+
+            output.write_all(&bytes).map_err(io_error(0, 0))?;
+            total_bytes += bytes.len();
+        }
+        let mut total_bytes_read = 0;
+        let mut block_index = 0;
+        let mut checksum = Xxh32::new(0);
+        let mut index = Vec::new();
+        loop
+        {
+            // fill whole blocks, so short reads do not change the output
+            let bytes_read =
+                read_full(&mut input, &mut buffer[history..history + batch_size])
+                    .map_err(io_error(block_index, total_bytes_read))?;
+            total_bytes_read += bytes_read;
+
+            if bytes_read == 0
+            {
+                break;
+            }
+            let end = history + bytes_read;
+            checksum.update(&buffer[history..end]);
+
+            pool.compress(&buffer[..end], history, |block, decompressed_size| {
+                output
+                    .write_all(block)
+                    .map_err(io_error(block_index, total_bytes))?;
+                index.push(IndexEntry {
+                    compressed_offset: total_bytes as u64,
+                    decompressed_size: decompressed_size as u32
+                });
+                total_bytes += block.len();
+                block_index += 1;
+                Ok(())
+            })?;
 
-        if bytes_read == 0
+            if bytes_read < batch_size
+            {
+                break;
+            }
+            // the end of this batch becomes the history of the next one
+            let keep = end.min(history_size);
+
+            buffer.copy_within(end - keep..end, 0);
+            history = keep;
+        }
+
+        if header.is_some() && content_size.is_some_and(|size| size != total_bytes_read as u64)
         {
-            break;
+            let error = std::io::Error::other("input changed size while it was compressed");
+            return Err(io_error(block_index, total_bytes_read)(error));
         }
+        if let Some(header) = header
+        {
+            let checksum = header.content_checksum.then(|| checksum.finish());
+            let trailer = end_of_stream(&header, checksum, &index);
 
-        let bytes_compressed = compress_block(&max_in[..bytes_read], &mut max_out[4..], &mut table);
+            output
+                .write_all(&trailer)
+                .map_err(io_error(block_index, total_bytes))?;
+            total_bytes += trailer.len();
+        }
+        output.flush().map_err(io_error(block_index, total_bytes))?;
 
-        max_out[0..4].copy_from_slice(&(bytes_compressed as u32).to_le_bytes());
+        Ok((total_bytes_read as u64, total_bytes as u64))
+    }
+}
 
-        table.reset();
-        total_bytes += bytes_compressed;
-        out_fd.write_all(&max_out[..bytes_compressed + 4]).unwrap();
+/// Read from `reader` until `buf` is full or the reader is at its end,
+/// returning the number of bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize>
+{
+    let mut filled = 0;
+
+    while filled < buf.len()
+    {
+        match reader.read(&mut buf[filled..])
+        {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted =>
+            {}
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(filled)
+}
+
+#[test]
+fn linked_blocks_round_trip()
+{
+    use std::io::Read;
+
+    use crate::decompress::{decompress_to_vec, decompress_to_vec_trusted, GlzReader};
+    use crate::utils::pseudo_random;
+
+    // a random chunk seen again in every later block,
+    // only linked blocks can match it
+    let data = pseudo_random(100_000, 0x1234_5678).repeat(8);
+
+    for level in [6, 8, 11]
+    {
+        let options = CompressOptions {
+            level,
+            linked: true,
+            ..Default::default()
+        };
+        let independent = compress_to_vec_with(
+            &data,
+            &CompressOptions {
+                linked: false,
+                ..options
+            }
+        );
+        let linked = compress_to_vec_with(&data, &options);
+
+        assert!(linked.len() < independent.len() / 2, "level {level}");
+        // the first block has no history to depend on
+        assert!(!BlockPrefix::from_bytes(linked[..4].try_into().unwrap()).linked);
+
+        assert_eq!(decompress_to_vec(&linked).unwrap(), data);
+        assert_eq!(unsafe { decompress_to_vec_trusted(&linked) }.unwrap(), data);
+
+        let mut out = Vec::new();
+        GlzReader::new(&linked[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
     }
+}
 
-    let end = Instant::now();
+#[test]
+fn dictionary_round_trip()
+{
+    use crate::decompress::{decompress_to_vec, decompress_with_dict};
 
-    println!(
-        "Compressed {} to {} in {:?}",
-        total_bytes_read,
-        total_bytes,
-        end - start
-    );
+    let message = |id: usize| {
+        format!(
+            r#"{{"id": {id}, "user": "player{}", "event": "level_complete", "score": {}, "tags": ["daily", "ranked"]}}"#,
+            id * 7 % 13,
+            id * 31 % 1000
+        )
+        .into_bytes()
+    };
+    let dictionary: Vec<u8> = (0..20).flat_map(message).collect();
+    let data = message(1234);
+
+    let options = CompressOptions::default();
+    let plain = compress_to_vec_with(&data, &options);
+    let with_dict = compress_with_dict(&data, &dictionary, &options);
+
+    assert!(with_dict.len() < plain.len() / 2);
+    assert_eq!(decompress_with_dict(&with_dict, &dictionary).unwrap(), data);
+
+    // the dictionary is checked in both directions
+    let err = decompress_with_dict(&with_dict, b"another dictionary").unwrap_err();
+    assert!(matches!(err, GlzError::DictionaryMismatch { expected: Some(_), found: Some(_), .. }));
+
+    let err = decompress_to_vec(&with_dict).unwrap_err();
+    assert!(matches!(err, GlzError::DictionaryMismatch { found: None, .. }));
+
+    let err = decompress_with_dict(&plain, &dictionary).unwrap_err();
+    assert!(matches!(err, GlzError::DictionaryMismatch { expected: None, .. }));
+}
+
+#[test]
+fn incompressible_blocks_are_stored()
+{
+    use std::io::Read;
+
+    use crate::decompress::{
+        decompress_to_vec, decompress_to_vec_parallel, decompress_to_vec_trusted, GlzReader
+    };
+    use crate::utils::pseudo_random;
+
+    // random blocks around a compressible one, and a short random tail
+    let mut data = pseudo_random(BLOCK_SIZE, 0x51f1_5e1d);
+    data.extend(b"compressible ".repeat(BLOCK_SIZE / 13 + 1).iter().take(BLOCK_SIZE));
+    data.extend(pseudo_random(BLOCK_SIZE + 100, 0x1d5e_f151));
+
+    for linked in [false, true]
+    {
+        let options = CompressOptions {
+            linked,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+
+        // four prefixes, and the compressible block shrinks to almost nothing
+        assert!(compressed.len() < 3 * BLOCK_SIZE + 100 + 1000);
+
+        let prefix = BlockPrefix::from_bytes(compressed[..4].try_into().unwrap());
+        assert!(prefix.stored && !prefix.linked && prefix.size == BLOCK_SIZE);
+
+        assert!(decompress_to_vec(&compressed).unwrap() == data);
+        assert!(unsafe { decompress_to_vec_trusted(&compressed) }.unwrap() == data);
+        assert!(decompress_to_vec_parallel(&compressed, 3).unwrap() == data);
+
+        let mut out = Vec::new();
+        GlzReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        assert!(out == data);
+    }
+}
+
+#[test]
+fn compressor_reuse_matches_fresh_streams()
+{
+    // inputs of every size, each leaving different state behind
+    let inputs: Vec<Vec<u8>> = vec![
+        b"The compressor is reused for every file. ".repeat(BLOCK_SIZE / 10),
+        b"tiny".to_vec(),
+        Vec::new(),
+        (0..BLOCK_SIZE + 5000).map(|i| (i * 7 + i / 300) as u8).collect()
+    ];
+
+    for (linked, threads) in [(false, 1), (true, 1), (false, 3)]
+    {
+        let options = CompressOptions {
+            linked,
+            threads,
+            block_checksums: true,
+            content_checksum: true,
+            ..Default::default()
+        };
+        let mut compressor = Compressor::new(&options);
+
+        for input in &inputs
+        {
+            let mut reused = Vec::new();
+            let mut fresh = Vec::new();
+
+            compressor.compress_stream(&input[..], &mut reused, None).unwrap();
+            compress_stream(&input[..], &mut fresh, &options, None).unwrap();
+
+            assert!(reused == fresh, "{} bytes, linked {linked}", input.len());
+        }
+    }
 }
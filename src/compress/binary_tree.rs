@@ -0,0 +1,281 @@
+use crate::compress::hash_chains::{three_match_length, HASH_THREE_LOG_SIZE, HASH_THREE_SIZE};
+use crate::compress::levels::CompressionParams;
+use crate::compress::Match;
+use crate::utils::{count, v_hash};
+
+const HASH_FOUR_LOG_SIZE: usize = 17;
+
+const HASH_FOUR_SIZE: usize = 1 << HASH_FOUR_LOG_SIZE;
+
+/// A binary tree match finder.
+///
+/// Positions sharing a 4 byte hash are kept in a binary search tree ordered by
+/// the bytes that follow them, most recent position at the root. Searching a position
+/// walks the tree once, re-rooting it at the new position, and sees the candidates
+/// sharing the longest prefixes with it, instead of the most recent ones a hash chain sees.
+///
+/// This keeps finding long matches on repetitive data, where hash chains grow
+/// long and `search_depth` cuts them before the best match is reached.
+///
+/// Every position has to be passed to either [`all_matches`](BtMatchFinder::all_matches)
+/// or [`skip`](BtMatchFinder::skip), in increasing order.
+pub struct BtMatchFinder
+{
+    bt_tab:     Box<[u32; HASH_FOUR_SIZE]>,
+    // most recent position of every 3 byte hash, for short matches
+    hb_tab:     Box<[u32; HASH_THREE_SIZE]>,
+    // left (smaller) and right (larger) child of every position
+    child_tab:  Vec<u32>,
+    min_length: usize,
+    params:     CompressionParams
+}
+
+impl BtMatchFinder
+{
+    /// create a new match finder for inputs of up to `buf_size` bytes
+    pub fn with_params(
+        buf_size: usize, min_length: usize, params: CompressionParams
+    ) -> BtMatchFinder
+    {
+        let b_tab = vec![0; HASH_FOUR_SIZE].into_boxed_slice();
+        let h_tab = vec![0; HASH_THREE_SIZE].into_boxed_slice();
+
+        BtMatchFinder {
+            bt_tab: b_tab.try_into().unwrap(),
+            hb_tab: h_tab.try_into().unwrap(),
+            child_tab: vec![0; 2 * buf_size],
+            min_length,
+            params
+        }
+    }
+
+    /// Parameters used by this match finder
+    pub fn params(&self) -> &CompressionParams
+    {
+        &self.params
+    }
+
+    /// Change the parameters used for the following blocks
+    pub fn set_params(&mut self, params: CompressionParams)
+    {
+        self.params = params;
+    }
+
+    pub fn reset(&mut self)
+    {
+        self.bt_tab.fill(0);
+        self.hb_tab.fill(0);
+    }
+
+    /// Insert `start` into the tree and collect the matches found there,
+    /// including a 3 byte match at a short offset.
+    ///
+    /// Every match in `matches` is longer than the one before it,
+    /// so the last one is the longest.
+    pub fn all_matches(&mut self, bytes: &[u8], start: usize, matches: &mut Vec<Match>)
+    {
+        matches.clear();
+
+        let hash = unsafe { v_hash::<3>(bytes.as_ptr().add(start), HASH_THREE_LOG_SIZE) };
+        let candidate = self.hb_tab[hash % HASH_THREE_SIZE] as usize;
+        let three_length = three_match_length(bytes, start, candidate);
+
+        self.hb_tab[hash % HASH_THREE_SIZE] = start as u32;
+        self.advance::<true>(bytes, start, matches);
+
+        // the short match goes first, if it is shorter than the others
+        if three_length != 0 && matches.first().is_none_or(|m| three_length < m.length)
+        {
+            matches.insert(
+                0,
+                Match {
+                    length: three_length,
+                    offset: start - candidate
+                }
+            );
+        }
+    }
+
+    /// Insert `start` into the tree without reporting matches.
+    ///
+    /// Returns the length of the longest match seen, in a long repeat the
+    /// following positions add little and callers may skip some of them.
+    pub fn skip(&mut self, bytes: &[u8], start: usize) -> usize
+    {
+        let hash = unsafe { v_hash::<3>(bytes.as_ptr().add(start), HASH_THREE_LOG_SIZE) };
+        self.hb_tab[hash % HASH_THREE_SIZE] = start as u32;
+
+        self.advance::<false>(bytes, start, &mut Vec::new())
+    }
+
+    fn advance<const RECORD: bool>(
+        &mut self, bytes: &[u8], start: usize, matches: &mut Vec<Match>
+    ) -> usize
+    {
+        assert!(start + 4 <= bytes.len());
+        assert!(2 * start < self.child_tab.len());
+
+        let curr_start = &bytes[start..];
+        // the walk stops on a match this long, which also
+        // keeps it from reading past the end of the input
+        let nice_length = self.params.nice_length.min(curr_start.len());
+
+        let hash = unsafe { v_hash::<4>(curr_start.as_ptr(), HASH_FOUR_LOG_SIZE) } % HASH_FOUR_SIZE;
+
+        let mut node = self.bt_tab[hash] as usize;
+        // position 0 marks an empty slot, so it is never inserted
+        self.bt_tab[hash] = start as u32;
+
+        // slots waiting for the next node smaller and larger than `start`
+        let mut pending_lt = 2 * start;
+        let mut pending_gt = 2 * start + 1;
+
+        // prefix `start` shares with every node on the smaller and larger side,
+        // the tree is ordered so a node shares at least the smaller of the two
+        let mut best_lt_length = 0;
+        let mut best_gt_length = 0;
+        let mut length = 0;
+
+        let mut best_length = self.min_length - 1;
+        let mut longest = 0;
+        let mut depth = self.params.search_depth;
+
+        while node != 0 && depth > 0
+        {
+            depth -= 1;
+
+            if bytes[node + length] == curr_start[length]
+            {
+                length = extend(bytes, node, start, length + 1, nice_length);
+                longest = longest.max(length);
+
+                if RECORD
+                {
+                    // a match with an offset too short for the format is a repeat
+                    // of 1 to 3 bytes, which also matches at its first period past 3
+                    let distance = start - node;
+                    let offset = distance * (3 / distance + 1);
+
+                    if offset < start
+                    {
+                        let mut found = length;
+
+                        if offset != distance || length >= nice_length
+                        {
+                            // report how far the match really goes
+                            found = extend(bytes, start - offset, start, 0, curr_start.len());
+                        }
+                        if found > best_length
+                        {
+                            best_length = found;
+                            matches.push(Match {
+                                length: found,
+                                offset
+                            });
+                        }
+                    }
+                }
+                if length >= nice_length
+                {
+                    // `start` replaces `node`, taking over its children
+                    self.child_tab[pending_lt] = self.child_tab[2 * node];
+                    self.child_tab[pending_gt] = self.child_tab[2 * node + 1];
+                    return longest;
+                }
+            }
+            if bytes[node + length] < curr_start[length]
+            {
+                // node goes to the smaller side, continue with its larger child
+                self.child_tab[pending_lt] = node as u32;
+                pending_lt = 2 * node + 1;
+                node = self.child_tab[pending_lt] as usize;
+                best_lt_length = length;
+            }
+            else
+            {
+                self.child_tab[pending_gt] = node as u32;
+                pending_gt = 2 * node;
+                node = self.child_tab[pending_gt] as usize;
+                best_gt_length = length;
+            }
+            length = best_lt_length.min(best_gt_length);
+        }
+        self.child_tab[pending_lt] = 0;
+        self.child_tab[pending_gt] = 0;
+
+        longest
+    }
+}
+
+/// Length of the match between `candidate` and `start`, knowing
+/// the first `known` bytes match, up to `limit`
+#[inline(always)]
+fn extend(bytes: &[u8], candidate: usize, start: usize, known: usize, limit: usize) -> usize
+{
+    let end = start + limit;
+    // count only compares whole 16 byte chunks, finish the tail byte by byte
+    let mut length = known + count(&bytes[candidate + known..end], &bytes[start + known..end]);
+
+    while length < limit && bytes[candidate + length] == bytes[start + length]
+    {
+        length += 1;
+    }
+    length
+}
+
+#[test]
+fn binary_tree_finds_longest_match()
+{
+    use crate::compress::Strategy;
+    use crate::utils::pseudo_random;
+
+    // random words from a small dictionary, repeated in random order
+    let mut random = pseudo_random(4096, 0x2545_f491).into_iter().map(usize::from);
+    let mut next = || random.next().unwrap();
+    let words: Vec<Vec<u8>> = (0..16)
+        .map(|_| (0..5 + next() % 40).map(|_| next() as u8).collect())
+        .collect();
+    let mut data = Vec::new();
+
+    while data.len() < 6000
+    {
+        data.extend_from_slice(&words[next() % words.len()]);
+    }
+    let params = CompressionParams {
+        search_depth: i32::MAX,
+        nice_length: 1 << 20,
+        strategy: Strategy::Optimal,
+        ..CompressionParams::default()
+    };
+    let mut finder = BtMatchFinder::with_params(data.len(), 3, params);
+    let mut matches = Vec::new();
+
+    for start in 0..data.len() - 4
+    {
+        finder.all_matches(&data, start, &mut matches);
+
+        let longest = (1..start.saturating_sub(3))
+            .map(|position| {
+                let length = data[position..]
+                    .iter()
+                    .zip(&data[start..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (length, start - position)
+            })
+            .max_by_key(|&(length, offset)| (length, usize::MAX - offset))
+            .unwrap_or((0, 0));
+
+        for found in &matches
+        {
+            assert_eq!(
+                data[start - found.offset..][..found.length],
+                data[start..][..found.length]
+            );
+        }
+        if longest.0 >= 4
+        {
+            assert_eq!(matches.last().map(|m| m.length), Some(longest.0), "{start}");
+        }
+    }
+}
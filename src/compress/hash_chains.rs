@@ -1,24 +1,128 @@
-use crate::compress::EncodeSequence;
-use crate::constants::{BLOCK_SIZE, GLZ_MIN_MATCH, WINDOW_SIZE};
+// the match finder keeps the casts it was written with
+#![allow(clippy::unnecessary_cast)]
+
+use crate::compress::levels::{CompressionParams, Strategy};
+use crate::compress::optimal::{compress_optimal, ParseBuffers};
+use crate::compress::{BtMatchFinder, EncodeSequence, Match};
+use crate::constants::{BLOCK_SIZE, GLZ_MIN_MATCH, HISTORY_SIZE, WINDOW_SIZE};
 use crate::utils::{compress_sequence, count, prefetch, v_hash};
 
 const HASH_FOUR_LOG_SIZE: usize = 17;
-const HASH_THREE_LOG_SIZE: usize = 15;
+pub(crate) const HASH_THREE_LOG_SIZE: usize = 15;
 const FIRST_BYTE_OFFSET: u32 = 24;
 
 const HASH_FOUR_SIZE: usize = 1 << HASH_FOUR_LOG_SIZE;
+pub(crate) const HASH_THREE_SIZE: usize = 1 << HASH_THREE_LOG_SIZE;
+/// Positions the hash chains can hold, a block and its history
+const CHAIN_SIZE: usize = BLOCK_SIZE + HISTORY_SIZE;
+
+/// Largest offset a 3 byte match is taken at.
+///
+/// Such a match only beats its 3 literals when the offset takes a single `encode_mod` byte
+const THREE_MATCH_MAX_OFFSET: usize = (0x7f << 2) | 0b11;
+
+/// Length of the match between `start` and `candidate` from a 3 byte hash table,
+/// or 0 if it is not a match worth taking
+#[inline(always)]
+pub(crate) fn three_match_length(bytes: &[u8], start: usize, candidate: usize) -> usize
+{
+    let offset = start.wrapping_sub(candidate);
+
+    if candidate == 0
+        || offset <= 3
+        || offset > THREE_MATCH_MAX_OFFSET
+        || bytes[candidate..candidate + 3] != bytes[start..start + 3]
+    {
+        return 0;
+    }
+    3 + count(&bytes[candidate + 3..], &bytes[start + 3..])
+}
 
-#[inline(never)]
-#[allow(clippy::too_many_lines, unused_assignments)]
 pub fn compress_block(src: &[u8], dest: &mut [u8], table: &mut HcMatchFinder) -> usize
 {
-    let mut window_start = 0;
+    compress_block_with_history(src, 0, dest, table)
+}
+
+/// Compress the block `src[history..]` into `dest`, letting its
+/// matches reach back into the history in `src[..history]`.
+///
+/// This is how blocks of a linked stream are compressed, the output
+/// must be decoded with the same history before it.
+#[inline(never)]
+pub fn compress_block_with_history(
+    src: &[u8], history: usize, dest: &mut [u8], table: &mut HcMatchFinder
+) -> usize
+{
+    let params = table.params;
+
+    if history > 0 && params.strategy != Strategy::Optimal
+    {
+        // the optimal parser may use a binary tree instead, and fills it itself
+        table.advance_four_match(src, 0, history);
+    }
+    match params.strategy
+    {
+        Strategy::Greedy => compress_lazy::<0>(src, history, dest, table, &params),
+        Strategy::Lazy => compress_lazy::<1>(src, history, dest, table, &params),
+        Strategy::Lazy2 => compress_lazy::<2>(src, history, dest, table, &params),
+        Strategy::Optimal => compress_optimal(src, history, dest, table, &params)
+    }
+}
+
+/// Write the literals left at the end of a block as the final sequence,
+/// returning the number of literals written.
+#[inline(always)]
+pub(crate) fn write_last_literals(
+    src: &[u8], dest: &mut [u8], out_position: &mut usize, literals: usize
+) -> usize
+{
+    let sequence = EncodeSequence {
+        start: src.len() - literals,
+        ll:    literals,
+        ml:    GLZ_MIN_MATCH,
+        ol:    10,
+        cost:  0
+    };
+    compress_sequence::<true>(src, dest, out_position, &sequence);
+
+    return literals;
+}
+
+/// Rough benefit of a match, longer matches win and far offsets cost
+/// more `encode_mod` bytes. The weights are zstd's lazy parser's,
+/// 4 for every matched byte against 1 for every bit of the offset
+#[inline(always)]
+fn match_gain(sequence: &EncodeSequence) -> isize
+{
+    (4 * sequence.ml) as isize - (usize::BITS - sequence.ol.leading_zeros()) as isize
+}
+
+/// How much more `match_gain` a later match needs than the current one to be taken,
+/// paying for the literal emitted in place of the current match. zstd's value, on text
+/// and binaries both 0 and 8 compressed worse at `Lazy2` and no better at `Lazy`
+const LAZY_THRESHOLD: isize = 4;
+
+/// Lazy matching.
+///
+/// After finding a match, look for a better one starting at the next position,
+/// and with `LOOKAHEAD` == 2 the one after it, emitting a literal instead of the
+/// current match whenever a later position wins. With `LOOKAHEAD` == 0 this is
+/// greedy matching, every match found is taken.
+#[inline(always)]
+#[allow(unused_assignments)]
+fn compress_lazy<const LOOKAHEAD: usize>(
+    src: &[u8], start: usize, dest: &mut [u8], table: &mut HcMatchFinder,
+    params: &CompressionParams
+) -> usize
+{
+    let mut window_start = start;
     let mut literals_before_match = 0;
-    let skip_literals = 1;
+    let mut skip_literals = 1;
     let mut out_position = 0;
     let mut compressed_bytes = 0;
 
     let mut sequence = EncodeSequence::default();
+    let mut candidate = EncodeSequence::default();
 
     'match_loop: loop
     {
@@ -33,13 +137,43 @@ pub fn compress_block(src: &[u8], dest: &mut [u8], table: &mut HcMatchFinder) ->
 
             if table.longest_four_match(src, window_start, literals_before_match, &mut sequence)
             {
-                sequence.ll = literals_before_match;
                 break 'inner_loop;
             }
 
             window_start += skip_literals;
             literals_before_match += skip_literals;
+            skip_literals = params.skip_step(literals_before_match);
         }
+        // see if deferring the match gives a better one
+        'lazy: loop
+        {
+            for step in 1..=LOOKAHEAD
+            {
+                if window_start + step + 1 + WINDOW_SIZE > src.len()
+                {
+                    break 'lazy;
+                }
+                candidate.ml = 0;
+
+                let found = table.longest_four_match(
+                    src,
+                    window_start + step,
+                    literals_before_match + step,
+                    &mut candidate
+                );
+
+                if found && match_gain(&candidate) > match_gain(&sequence) + LAZY_THRESHOLD
+                {
+                    sequence = candidate;
+                    window_start += step;
+                    literals_before_match += step;
+                    continue 'lazy;
+                }
+            }
+            break 'lazy;
+        }
+        sequence.ll = literals_before_match;
+
         compressed_bytes += sequence.ll + sequence.ml;
         compress_sequence::<false>(src, dest, &mut out_position, &sequence);
 
@@ -49,6 +183,7 @@ pub fn compress_block(src: &[u8], dest: &mut [u8], table: &mut HcMatchFinder) ->
         window_start += sequence.ml as usize;
 
         sequence.ml = 0;
+        skip_literals = 1;
 
         if window_start + WINDOW_SIZE + skip_literals > src.len()
         {
@@ -56,36 +191,36 @@ pub fn compress_block(src: &[u8], dest: &mut [u8], table: &mut HcMatchFinder) ->
             break 'match_loop;
         }
     }
-    {
-        assert_eq!(sequence.ml, 0);
-
-        sequence.ll = src
-            .len()
-            .wrapping_sub(window_start)
-            .wrapping_add(literals_before_match);
+    assert_eq!(sequence.ml, 0);
+    compressed_bytes += write_last_literals(
+        src,
+        dest,
+        &mut out_position,
+        src.len() - window_start + literals_before_match
+    );
 
-        sequence.ol = 10;
-        sequence.start = src.len() - (sequence.ll as usize);
-        sequence.ml = GLZ_MIN_MATCH;
-        compress_sequence::<true>(src, dest, &mut out_position, &sequence);
-
-        compressed_bytes += sequence.ll as usize;
-    }
     table.reset();
-    assert_eq!(compressed_bytes, src.len());
+    assert_eq!(compressed_bytes, src.len() - start);
 
     return out_position;
 }
 
 pub struct HcMatchFinder
 {
-    next_hash:    [usize; 2],
-    hc_tab:       [u32; 1 << HASH_FOUR_LOG_SIZE],
-    hb_tab:       [u32; 1 << HASH_THREE_LOG_SIZE],
-    next_tab:     Box<[u32; BLOCK_SIZE]>,
-    search_depth: i32,
-    min_length:   usize,
-    nice_length:  usize
+    next_hash:          [usize; 2],
+    // position next_hash was computed for
+    next_hash_position: usize,
+    // first position not yet inserted into the hash chains
+    next_insert:        usize,
+    hc_tab:             [u32; 1 << HASH_FOUR_LOG_SIZE],
+    hb_tab:             [u32; 1 << HASH_THREE_LOG_SIZE],
+    next_tab:           Box<[u32; CHAIN_SIZE]>,
+    // allocated on first use, by parameters using MatchFinder::BinaryTree
+    binary_tree:        Option<Box<BtMatchFinder>>,
+    // nodes of Strategy::Optimal, kept for the next block
+    parse_buffers:      ParseBuffers,
+    min_length:         usize,
+    params:             CompressionParams
 }
 
 impl HcMatchFinder
@@ -95,29 +230,215 @@ impl HcMatchFinder
         buf_size: usize, search_depth: i32, min_length: usize, nice_length: usize
     ) -> HcMatchFinder
     {
-        let n_tab = vec![0; buf_size].into_boxed_slice();
+        let params = CompressionParams {
+            search_depth,
+            nice_length,
+            ..CompressionParams::default()
+        };
+        HcMatchFinder::with_params(buf_size, min_length, params)
+    }
+
+    /// create a new match finder for the given compression parameters,
+    /// for blocks of `buf_size` bytes with up to `HISTORY_SIZE` bytes of history
+    pub fn with_params(
+        buf_size: usize, min_length: usize, params: CompressionParams
+    ) -> HcMatchFinder
+    {
+        let n_tab = vec![0; buf_size + HISTORY_SIZE].into_boxed_slice();
         //debug_assert!(min_length == 4);
         HcMatchFinder {
             next_hash: [0, 0],
+            next_hash_position: usize::MAX,
+            next_insert: 0,
             hc_tab: [0; 1 << HASH_FOUR_LOG_SIZE],
             hb_tab: [0; 1 << HASH_THREE_LOG_SIZE],
             next_tab: n_tab.try_into().expect("Uh oh, fix values bro :)"),
-            search_depth,
-            nice_length,
-            min_length
+            binary_tree: None,
+            parse_buffers: ParseBuffers::default(),
+            min_length,
+            params
         }
     }
 
+    /// Parameters used by this match finder and by `compress_block`
+    pub fn params(&self) -> &CompressionParams
+    {
+        &self.params
+    }
+
+    /// Change the parameters used for the following blocks
+    pub fn set_params(&mut self, params: CompressionParams)
+    {
+        self.params = params;
+    }
+
     pub fn reset(&mut self)
     {
         self.hc_tab.fill(0);
         self.hb_tab.fill(0);
         self.next_hash.fill(0);
+        self.next_hash_position = usize::MAX;
+        self.next_insert = 0;
+
+        if let Some(binary_tree) = &mut self.binary_tree
+        {
+            binary_tree.reset();
+        }
+    }
+
+    /// Take the buffers of the optimal parser, so it can borrow this match finder
+    /// while using them. Hand them back with `keep_parse_buffers`
+    pub(crate) fn take_parse_buffers(&mut self) -> ParseBuffers
+    {
+        std::mem::take(&mut self.parse_buffers)
+    }
+
+    pub(crate) fn keep_parse_buffers(&mut self, buffers: ParseBuffers)
+    {
+        self.parse_buffers = buffers;
+    }
+
+    /// The binary tree match finder, using the same parameters as this one
+    pub(crate) fn binary_tree(&mut self) -> &mut BtMatchFinder
+    {
+        let binary_tree = self.binary_tree.get_or_insert_with(|| {
+            Box::new(BtMatchFinder::with_params(
+                CHAIN_SIZE,
+                self.min_length,
+                self.params
+            ))
+        });
+        binary_tree.set_params(self.params);
+        binary_tree
+    }
+
+    /// Insert `start` into the hash chains and collect the matches found there.
+    ///
+    /// Every match in `matches` is longer than the one before it,
+    /// so the last one is the longest. The search stops early once a match
+    /// longer than the nice length is found.
+    pub fn all_four_matches(&mut self, bytes: &[u8], start: usize, matches: &mut Vec<Match>)
+    {
+        matches.clear();
+
+        let candidate = self.insert_three(bytes, start);
+        let three_length = three_match_length(bytes, start, candidate);
+
+        let curr_start = &bytes[start..];
+        let curr_match_byte = usize::from(curr_start[0]);
+        let curr_byte = u32::from(curr_start[0]) << FIRST_BYTE_OFFSET;
+
+        if self.next_hash_position != start
+        {
+            self.next_hash[1] = unsafe { v_hash::<4>(curr_start.as_ptr(), HASH_FOUR_LOG_SIZE) };
+        }
+        let hash = self.next_hash[1];
+        let mut cur_offset = self.hc_tab[hash % HASH_FOUR_SIZE] as usize;
+
+        self.hc_tab[hash % HASH_FOUR_SIZE] = curr_byte | (start as u32);
+        self.next_tab[start % CHAIN_SIZE] = cur_offset as u32;
+
+        self.next_hash[1] = unsafe { v_hash::<4>(curr_start.as_ptr().add(1), HASH_FOUR_LOG_SIZE) };
+        self.next_hash_position = start + 1;
+        self.next_insert = start + 1;
+
+        let mut best_length = self.min_length - 1;
+        let mut depth = self.params.search_depth;
+
+        while depth > 0
+        {
+            let first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
+            let position = cur_offset & ((1 << FIRST_BYTE_OFFSET) - 1);
+
+            if position == 0
+            {
+                break;
+            }
+            depth -= 1;
+
+            // check the first byte, and the byte that would make this match
+            // longer than the best one before counting
+            if first_match_byte == curr_match_byte
+                && start - position > 3
+                && best_length < curr_start.len()
+                && bytes[position + best_length] == curr_start[best_length]
+            {
+                let length = count(&bytes[position..], curr_start);
+
+                if length > best_length
+                {
+                    best_length = length;
+                    matches.push(Match {
+                        length,
+                        offset: start - position
+                    });
+
+                    if length > self.params.nice_length
+                    {
+                        break;
+                    }
+                }
+            }
+            cur_offset = self.next_tab[position % CHAIN_SIZE] as usize;
+        }
+        // the short match goes first, if it is shorter than the others
+        if three_length != 0 && matches.first().is_none_or(|m| three_length < m.length)
+        {
+            matches.insert(
+                0,
+                Match {
+                    length: three_length,
+                    offset: start - candidate
+                }
+            );
+        }
     }
+
+    /// Insert `start` into the 3 byte hash table,
+    /// returning the previous position with the same hash
+    #[inline(always)]
+    fn insert_three(&mut self, bytes: &[u8], start: usize) -> usize
+    {
+        let hash = unsafe { v_hash::<3>(bytes.as_ptr().add(start), HASH_THREE_LOG_SIZE) };
+        let previous = self.hb_tab[hash % HASH_THREE_SIZE];
+
+        self.hb_tab[hash % HASH_THREE_SIZE] = start as u32;
+
+        previous as usize
+    }
+
+    /// Find the longest match at `start` in the hash chains, or failing that
+    /// a 3 byte match at a short offset, storing it in `sequence`.
+    ///
+    /// Returns `true` if a match was found
     #[inline(always)]
     pub fn longest_four_match(
         &mut self, bytes: &[u8], start: usize, literal_length: usize, sequence: &mut EncodeSequence
     ) -> bool
+    {
+        let candidate = self.insert_three(bytes, start);
+
+        if self.longest_chain_match(bytes, start, literal_length, sequence)
+        {
+            return true;
+        }
+        let length = three_match_length(bytes, start, candidate);
+
+        if length > sequence.ml
+        {
+            sequence.ml = length;
+            sequence.ol = start - candidate;
+            sequence.start = start - literal_length;
+
+            return true;
+        }
+        return false;
+    }
+
+    #[inline(always)]
+    fn longest_chain_match(
+        &mut self, bytes: &[u8], start: usize, literal_length: usize, sequence: &mut EncodeSequence
+    ) -> bool
     {
         let curr_start = &bytes[start..];
         // store the current first byte in the hash, we use this to
@@ -127,13 +448,17 @@ impl HcMatchFinder
         let curr_byte = u32::from(curr_start[0]) << FIRST_BYTE_OFFSET;
 
         let next_window = unsafe { bytes.as_ptr().add(start + 1) };
-        /* Get the precomputed hash codes */
+        /* Get the precomputed hash codes, unless positions were skipped since they were computed */
+        if self.next_hash_position != start
+        {
+            self.next_hash[1] = unsafe { v_hash::<4>(curr_start.as_ptr(), HASH_FOUR_LOG_SIZE) };
+        }
         let hash = self.next_hash[1];
         /* From the hash buckets, get the first node of each linked list. */
         let mut cur_offset = self.hc_tab[hash % HASH_FOUR_SIZE] as usize;
 
         self.hc_tab[hash % HASH_FOUR_SIZE] = curr_byte | (start as u32);
-        self.next_tab[start % BLOCK_SIZE] = cur_offset as u32;
+        self.next_tab[start % CHAIN_SIZE] = cur_offset as u32;
 
         //  compute the next hash codes
         let n_hash4 = unsafe { v_hash::<4>(next_window, HASH_FOUR_LOG_SIZE) };
@@ -141,6 +466,8 @@ impl HcMatchFinder
         prefetch(bytes.as_ptr(), cur_offset);
 
         self.next_hash[1] = n_hash4 as usize;
+        self.next_hash_position = start + 1;
+        self.next_insert = start + 1;
         let mut match_found = false;
 
         if cur_offset != 0
@@ -150,7 +477,7 @@ impl HcMatchFinder
 
             cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
 
-            let mut depth = self.search_depth;
+            let mut depth = self.params.search_depth;
 
             'outer: loop
             {
@@ -172,7 +499,7 @@ impl HcMatchFinder
                         break 'inner;
                     }
 
-                    cur_offset = self.next_tab[cur_offset % BLOCK_SIZE] as usize;
+                    cur_offset = self.next_tab[cur_offset % CHAIN_SIZE] as usize;
                     first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
                     cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
 
@@ -185,18 +512,22 @@ impl HcMatchFinder
                 }
                 if match_found
                 {
+                    if sequence.ml >= curr_start.len()
+                    {
+                        // the match already runs to the end of the input
+                        return true;
+                    }
                     unsafe {
                         // we have a previous match, check if current match length will go past
                         // the previous match length by looking at the byte in current length plus 1
                         // if they match, then this has the potential to beat the previous ML
                         let prev_match_end = bytes.get_unchecked(cur_offset + sequence.ml);
-                        // N.B: This may read +1 byte past curr_start, but that is okay
                         let curr_match_end = curr_start.get_unchecked(sequence.ml);
 
                         if prev_match_end != curr_match_end
                         {
                             // go to next node
-                            cur_offset = self.next_tab[cur_offset % BLOCK_SIZE] as usize;
+                            cur_offset = self.next_tab[cur_offset % CHAIN_SIZE] as usize;
                             first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
                             cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
 
@@ -219,13 +550,13 @@ impl HcMatchFinder
 
                     match_found = true;
 
-                    if new_match_length > self.nice_length
+                    if new_match_length > self.params.nice_length
                     {
                         return true;
                     }
                 }
                 // go to next node
-                cur_offset = self.next_tab[cur_offset % BLOCK_SIZE] as usize;
+                cur_offset = self.next_tab[cur_offset % CHAIN_SIZE] as usize;
                 first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
                 cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
 
@@ -235,34 +566,45 @@ impl HcMatchFinder
         return match_found;
     }
 
+    /// Insert the positions covered by a match of `length` bytes at `start` into the hash chains.
+    ///
+    /// `start` itself, and any position after it already searched by a lazy parser,
+    /// is expected to have been inserted by [`longest_four_match`](Self::longest_four_match)
     #[inline(always)]
-    pub fn advance_four_match(&mut self, window_start: &[u8], mut start: usize, mut length: usize)
+    pub fn advance_four_match(&mut self, window_start: &[u8], start: usize, length: usize)
     {
-        if (start + length + 100) < window_start.len()
+        let end = start + length;
+
+        if (end + WINDOW_SIZE) < window_start.len()
         {
-            let mut hash4 = self.next_hash[1];
-            loop
+            let mut position = (start + 1).max(self.next_insert);
+            let mut hash4 = if self.next_hash_position == position
+            {
+                self.next_hash[1]
+            }
+            else
+            {
+                unsafe { v_hash::<4>(window_start.as_ptr().add(position), HASH_FOUR_LOG_SIZE) }
+            };
+            while position < end
             {
                 unsafe {
-                    let next_window = window_start.as_ptr().add(start + 1);
+                    let next_window = window_start.as_ptr().add(position + 1);
 
                     let curr_byte =
-                        u32::from(*window_start.get_unchecked(start)) << FIRST_BYTE_OFFSET;
+                        u32::from(*window_start.get_unchecked(position)) << FIRST_BYTE_OFFSET;
 
-                    self.next_tab[start % BLOCK_SIZE] = self.hc_tab[hash4 % HASH_FOUR_SIZE];
-                    self.hc_tab[hash4 % HASH_FOUR_SIZE] = curr_byte | (start as u32);
-                    start += 1;
+                    self.next_tab[position % CHAIN_SIZE] = self.hc_tab[hash4 % HASH_FOUR_SIZE];
+                    self.hc_tab[hash4 % HASH_FOUR_SIZE] = curr_byte | (position as u32);
+                    self.insert_three(window_start, position);
+                    position += 1;
                     //  compute the next hash codes
                     hash4 = v_hash::<4>(next_window, HASH_FOUR_LOG_SIZE) as usize;
-                    length -= 1;
-
-                    if length <= 0
-                    {
-                        break;
-                    }
                 }
             }
-            self.next_hash[1] = hash4 as usize;
+            self.next_hash[1] = hash4;
+            self.next_hash_position = end;
+            self.next_insert = end;
             prefetch(self.hc_tab.as_ptr(), hash4);
         }
     }
@@ -272,10 +614,61 @@ impl HcMatchFinder
 fn compress_decompress_encodemod()
 {
     use crate::decompress::decode_encode_mod;
+    use crate::utils::compress_encode_mod;
 
     let mut out = [0; 16];
     let value = 13942;
     compress_encode_mod(value, &mut out, &mut 0);
-    let recovered = decode_encode_mod(&mut out);
+    let recovered = decode_encode_mod(&out);
     assert_eq!(recovered.0, value);
 }
+
+#[test]
+fn encode_mod_len_matches_written_bytes()
+{
+    use crate::utils::{compress_encode_mod, encode_mod_len};
+
+    for value in [
+        0,
+        1,
+        0x7f,
+        0x80,
+        0xff,
+        0x3fff,
+        0x4000,
+        0x4080,
+        1 << 20,
+        1 << 28
+    ]
+    {
+        let mut out = [0; 16];
+        let mut written = 0;
+        compress_encode_mod(value, &mut out, &mut written);
+        assert_eq!(encode_mod_len(value), written, "{value}");
+    }
+}
+
+#[test]
+fn three_byte_match_at_short_offset()
+{
+    // "abc" repeats 8 bytes apart, nothing longer does
+    let mut data = b"xabc12345abc67890".to_vec();
+    data.resize(64, 0);
+
+    let mut table =
+        HcMatchFinder::with_params(BLOCK_SIZE, GLZ_MIN_MATCH, CompressionParams::default());
+    let mut sequence = EncodeSequence::default();
+
+    table.advance_four_match(&data, 0, 9);
+    assert!(table.longest_four_match(&data, 9, 0, &mut sequence));
+    assert_eq!((sequence.ml, sequence.ol), (3, 8));
+
+    // and is not taken when too far away to pay off
+    data.splice(5..5, [b'-'; THREE_MATCH_MAX_OFFSET]);
+    table.reset();
+    sequence = EncodeSequence::default();
+
+    let start = 9 + THREE_MATCH_MAX_OFFSET;
+    table.advance_four_match(&data, 0, start);
+    assert!(!table.longest_four_match(&data, start, 0, &mut sequence));
+}
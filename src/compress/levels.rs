@@ -0,0 +1,177 @@
+use crate::constants::DEPTH_STRIDE;
+
+/// Lowest (fastest) compression level
+pub const MIN_LEVEL: u8 = 1;
+/// Highest (strongest) compression level
+pub const MAX_LEVEL: u8 = 12;
+/// Level used when none is given
+pub const DEFAULT_LEVEL: u8 = 6;
+
+/// How the compressor turns the matches it finds into sequences
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy
+{
+    /// Emit the first match found at a position
+    Greedy,
+    /// Defer a match while the next position has a better one
+    Lazy,
+    /// Like `Lazy`, but also looks two positions ahead
+    Lazy2,
+    /// Collect every match candidate and pick the cheapest parse of the block
+    /// by its encoded size.
+    ///
+    /// Keeps a node for every position of the block, about 10 MB per thread on top
+    /// of the match finder, allocated once and reused by the blocks after
+    Optimal
+}
+
+/// Data structure used to find matches
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MatchFinder
+{
+    /// Chains of the positions sharing a hash, most recent first
+    HashChains,
+    /// Binary trees of the positions sharing a hash, see
+    /// [`BtMatchFinder`](crate::compress::BtMatchFinder).
+    /// Only used by [`Strategy::Optimal`], other strategies use hash chains
+    BinaryTree
+}
+
+/// Parameters controlling the match finder and parser
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressionParams
+{
+    /// How many hash chain entries are visited per position
+    pub search_depth: i32,
+    /// Stop searching once a match longer than this is found
+    pub nice_length:  usize,
+    /// After `2^skip_trigger` literals without a match, the compressor
+    /// starts skipping positions, one more for every further `2^skip_trigger` literals.
+    /// Values past 63 disable skipping
+    pub skip_trigger: u32,
+    pub strategy:     Strategy,
+    pub match_finder: MatchFinder
+}
+
+impl CompressionParams
+{
+    /// Parameters for compression `level`, levels outside
+    /// `MIN_LEVEL..=MAX_LEVEL` are clamped to that range.
+    pub const fn from_level(level: u8) -> CompressionParams
+    {
+        // (search depth, nice length, skip trigger, strategy)
+        let (search_depth, nice_length, skip_trigger, strategy) = match level
+        {
+            0 | 1 => (1, 16, 3, Strategy::Greedy),
+            2 => (2, 24, 4, Strategy::Greedy),
+            3 => (4, 32, 5, Strategy::Greedy),
+            4 => (8, 48, 6, Strategy::Greedy),
+            5 => (12, 64, 8, Strategy::Greedy),
+            6 => (DEPTH_STRIDE, 100, u32::MAX, Strategy::Greedy),
+            7 => (24, 128, u32::MAX, Strategy::Lazy),
+            8 => (48, 192, u32::MAX, Strategy::Lazy),
+            9 => (64, 256, u32::MAX, Strategy::Lazy2),
+            10 => (128, 512, u32::MAX, Strategy::Lazy2),
+            11 => (32, 512, u32::MAX, Strategy::Optimal),
+            _ => (512, 8192, u32::MAX, Strategy::Optimal)
+        };
+        // binary trees see the best candidates first,
+        // so the optimal levels use them
+        let match_finder = match strategy
+        {
+            Strategy::Optimal => MatchFinder::BinaryTree,
+            _ => MatchFinder::HashChains
+        };
+        CompressionParams {
+            search_depth,
+            nice_length,
+            skip_trigger,
+            strategy,
+            match_finder
+        }
+    }
+
+    /// Number of positions to move forward after a failed search,
+    /// when `literals` literals have been seen since the last match
+    #[inline(always)]
+    pub(crate) fn skip_step(&self, literals: usize) -> usize
+    {
+        1 + literals.checked_shr(self.skip_trigger).unwrap_or(0)
+    }
+}
+
+impl Default for CompressionParams
+{
+    fn default() -> Self
+    {
+        CompressionParams::from_level(DEFAULT_LEVEL)
+    }
+}
+
+#[test]
+fn all_levels_round_trip()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::decompress::decompress_to_vec;
+
+    let data = b"Levels trade speed for ratio, levels trade ratio for speed. ".repeat(5000);
+
+    for level in MIN_LEVEL..=MAX_LEVEL
+    {
+        let options = CompressOptions {
+            level,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+        assert_eq!(
+            decompress_to_vec(&compressed).unwrap(),
+            data,
+            "level {level}"
+        );
+    }
+    for strategy in [
+        Strategy::Greedy,
+        Strategy::Lazy,
+        Strategy::Lazy2,
+        Strategy::Optimal
+    ]
+    {
+        let options = CompressOptions {
+            strategy: Some(strategy),
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+        assert_eq!(
+            decompress_to_vec(&compressed).unwrap(),
+            data,
+            "{strategy:?}"
+        );
+    }
+}
+
+#[test]
+fn lazy_parsing_beats_greedy_on_text()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::decompress::decompress_to_vec;
+    use crate::utils::pseudo_text;
+
+    let data = pseudo_text(200_000, 0x7e47_5eed);
+    let size = |strategy| {
+        let options = CompressOptions {
+            strategy: Some(strategy),
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), data, "{strategy:?}");
+        compressed.len()
+    };
+    let greedy = size(Strategy::Greedy);
+    let lazy = size(Strategy::Lazy);
+    let lazy2 = size(Strategy::Lazy2);
+
+    // about 11% and 13% smaller when this was written
+    assert!(lazy < greedy * 95 / 100, "lazy {lazy}, greedy {greedy}");
+    assert!(lazy2 < lazy, "lazy2 {lazy2}, lazy {lazy}");
+}
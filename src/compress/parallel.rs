@@ -0,0 +1,175 @@
+use std::thread;
+
+use crate::compress::hash_chains::HcMatchFinder;
+use crate::compress::{BlockWindow, CompressOptions, MAX_BLOCK_OUTPUT};
+use crate::constants::{BLOCK_SIZE, HISTORY_SIZE};
+
+/// State a thread needs to compress one block
+struct Worker
+{
+    table:   Box<HcMatchFinder>,
+    window:  BlockWindow,
+    // compressed block, with its length prefix and checksum
+    max_out: Vec<u8>,
+    written: usize
+}
+
+/// Compresses batches of blocks, one block per worker and thread.
+///
+/// Blocks only depend on their own data and for linked streams the input before them,
+/// never on how earlier blocks were compressed, since the match finder is reset after
+/// every block. So the output is the same whatever the number of workers.
+///
+/// Every batch runs in a thread scope of its own and ends with its slowest block,
+/// so threads done early wait for the rest of the batch. That is deliberate: blocks
+/// are written in order anyway, all blocks but the last are the same size, and spawning
+/// a thread costs microseconds against the milliseconds a block takes
+pub(crate) struct BlockPool
+{
+    // created once a batch has a block for them, up to `threads`
+    workers: Vec<Worker>,
+    threads: usize,
+    options: CompressOptions
+}
+
+impl BlockPool
+{
+    /// A pool of `options.threads` workers, but no more than there are cores.
+    ///
+    /// Each worker holds a match finder and buffers of several megabytes, so they are
+    /// only created when a batch needs them, a small input gets a single one
+    pub fn new(options: &CompressOptions) -> BlockPool
+    {
+        let cores = thread::available_parallelism().map_or(1, usize::from);
+
+        BlockPool {
+            workers: Vec::new(),
+            threads: options.threads.clamp(1, cores),
+            options: *options
+        }
+    }
+
+    /// Number of bytes compressed by one call to [`compress`](Self::compress)
+    pub fn batch_size(&self) -> usize
+    {
+        self.threads * BLOCK_SIZE
+    }
+
+    /// Compress `data[start..]`, at most [`batch_size`](Self::batch_size) bytes, as blocks of
+    /// `BLOCK_SIZE` bytes and pass them with their length prefixes and checksums to `output`
+    /// in order, along with the number of bytes each one decodes to.
+    ///
+    /// For linked streams `data[..start]` is the input before the batch,
+    /// of which the last `HISTORY_SIZE` bytes are used.
+    pub fn compress<E>(
+        &mut self, data: &[u8], start: usize,
+        mut output: impl FnMut(&[u8], usize) -> Result<(), E>
+    ) -> Result<(), E>
+    {
+        debug_assert!(data.len() - start <= self.batch_size());
+
+        let blocks = (data.len() - start).div_ceil(BLOCK_SIZE);
+
+        while self.workers.len() < blocks
+        {
+            self.workers.push(Worker::new(&self.options));
+        }
+        let linked = self.options.links_blocks();
+        let blocks = data[start..].chunks(BLOCK_SIZE).enumerate();
+        let jobs = self.workers.iter_mut().zip(blocks).map(|(worker, (index, block))| {
+            let block_start = start + index * BLOCK_SIZE;
+            let history = if linked { block_start.min(HISTORY_SIZE) } else { 0 };
+
+            (worker, &data[block_start - history..block_start], block)
+        });
+
+        if data.len() - start <= BLOCK_SIZE
+        {
+            // a single block is not worth a thread
+            jobs.for_each(|(worker, history, block)| worker.compress(history, block));
+        }
+        else
+        {
+            thread::scope(|scope| {
+                for (worker, history, block) in jobs
+                {
+                    scope.spawn(move || worker.compress(history, block));
+                }
+            });
+        }
+        let blocks = data[start..].chunks(BLOCK_SIZE);
+
+        for (worker, block) in self.workers.iter().zip(blocks)
+        {
+            output(&worker.max_out[..worker.written], block.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Worker
+{
+    fn new(options: &CompressOptions) -> Worker
+    {
+        Worker {
+            table:   Box::new(options.match_finder()),
+            window:  BlockWindow::new(options),
+            max_out: vec![0; MAX_BLOCK_OUTPUT],
+            written: 0
+        }
+    }
+
+    fn compress(&mut self, history: &[u8], block: &[u8])
+    {
+        // compress_block may read a few bytes past the end of its input, so the block
+        // is copied to a buffer with slack bytes instead of being compressed in place
+        self.window.load(history, block);
+        self.written = self.window.compress(&mut self.max_out, &mut self.table);
+    }
+}
+
+#[test]
+fn threads_do_not_change_the_output()
+{
+    use crate::compress::compress_to_vec_with;
+    use crate::utils::pseudo_random;
+
+    // compressible, but different in every block
+    let data: Vec<u8> = pseudo_random(5 * BLOCK_SIZE + 1000, 0x7f4a_7c15)
+        .into_iter()
+        .enumerate()
+        .map(|(i, byte)| if byte & 3 == 0 { byte } else { (i / 1000) as u8 })
+        .collect();
+
+    for linked in [false, true]
+    {
+        let options = CompressOptions {
+            linked,
+            ..Default::default()
+        };
+        let single = compress_to_vec_with(&data, &options);
+
+        for threads in [2, 4, 8]
+        {
+            let parallel = compress_to_vec_with(&data, &CompressOptions { threads, ..options });
+            assert!(parallel == single, "{threads} threads, linked {linked}");
+        }
+    }
+}
+
+#[test]
+fn pools_only_create_the_workers_they_need()
+{
+    let options = CompressOptions {
+        threads: 1000,
+        ..Default::default()
+    };
+    let mut pool = BlockPool::new(&options);
+    let cores = thread::available_parallelism().map_or(1, usize::from);
+
+    assert_eq!(pool.batch_size(), 1000.min(cores) * BLOCK_SIZE);
+
+    // a small input gets one worker, whatever the number of threads
+    pool.compress(b"ten bytes!", 0, |_, _| Ok::<_, ()>(())).unwrap();
+    assert_eq!(pool.workers.len(), 1);
+}
@@ -0,0 +1,250 @@
+use crate::utils::{encode_mod_len, v_hash};
+
+/// Length of the substrings counted.
+///
+/// A match into the dictionary pays a token and an `encode_mod` offset of two or
+/// three bytes, so shorter substrings save little even though 3 byte matches are allowed
+const SUBSTRING_LENGTH: usize = 6;
+
+const HASH_LOG_SIZE: usize = 20;
+
+const HASH_SIZE: usize = 1 << HASH_LOG_SIZE;
+
+/// Positions too close to the end of their sample to hash
+const NO_HASH: u32 = u32::MAX;
+
+/// Longest piece of a sample copied into the dictionary at once
+const SEGMENT_SIZE: usize = 64;
+
+/// Build a dictionary of at most `size` bytes from `samples`, for
+/// [`compress_with_dict`](crate::compress::compress_with_dict).
+///
+/// Substrings are counted by the number of samples they occur in, those in a single sample
+/// are ignored. The samples are split into one epoch per `SEGMENT_SIZE` bytes of dictionary
+/// and each epoch contributes the segment covering the most frequent substrings not yet in
+/// the dictionary, if sharing it saves more than the matches into it cost.
+///
+/// The segments saving the most go last, where the offsets into the dictionary are shortest.
+/// If the samples add up to less than `size` they are simply concatenated.
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], size: usize) -> Vec<u8>
+{
+    let total: usize = samples.iter().map(|s| s.as_ref().len()).sum();
+
+    if total <= size
+    {
+        return samples.iter().flat_map(|s| s.as_ref()).copied().collect();
+    }
+    let mut data = Vec::with_capacity(total);
+    // hash of the substring starting at every position of `data`
+    let mut hashes = Vec::with_capacity(total);
+    // number of samples each substring occurs in
+    let mut frequencies = vec![0_u32; HASH_SIZE];
+    let mut last_sample = vec![u32::MAX; HASH_SIZE];
+
+    for (index, sample) in samples.iter().enumerate()
+    {
+        let sample = sample.as_ref();
+
+        for position in 0..sample.len()
+        {
+            // v_hash reads 8 bytes for a 6 byte substring
+            if position + 8 > sample.len()
+            {
+                hashes.push(NO_HASH);
+                continue;
+            }
+            let hash = unsafe { v_hash::<6>(sample.as_ptr().add(position), HASH_LOG_SIZE) };
+
+            if last_sample[hash] != index as u32
+            {
+                last_sample[hash] = index as u32;
+                frequencies[hash] += 1;
+            }
+            hashes.push(hash as u32);
+        }
+        data.extend_from_slice(sample);
+    }
+
+    // a match into the dictionary costs a token and an offset
+    let match_cost = 1 + encode_mod_len(size >> 2);
+    let epochs = (size / SEGMENT_SIZE).max(1);
+    let epoch_size = total / epochs;
+
+    let mut in_window = vec![0_u8; HASH_SIZE];
+    let mut segments = Vec::new();
+
+    for epoch in 0..epochs
+    {
+        let begin = epoch * epoch_size;
+        let end = if epoch + 1 == epochs { total } else { begin + epoch_size };
+
+        let start = begin + best_segment(&hashes[begin..end], &frequencies, &mut in_window);
+        let segment = start..(start + SEGMENT_SIZE).min(end);
+
+        // trim the segment to the substrings worth keeping
+        let useful = |&position: &usize| value(&frequencies, hashes[position]) > 0;
+
+        let Some(first) = segment.clone().find(useful)
+        else
+        {
+            continue;
+        };
+        let last = segment.clone().rfind(useful).unwrap();
+
+        let mut score = 0;
+        let mut max_frequency = 0;
+
+        for &hash in &hashes[first..=last]
+        {
+            score += value(&frequencies, hash);
+
+            if hash != NO_HASH
+            {
+                max_frequency = max_frequency.max(frequencies[hash as usize] as usize);
+                // substrings in the dictionary are not worth picking again
+                frequencies[hash as usize] = 0;
+            }
+        }
+        // every sample sharing the segment saves the bytes it covers,
+        // a substring per byte plus the tail of the last one, minus one match
+        let saving = (score + max_frequency * (SUBSTRING_LENGTH - 1)) as isize
+            - (max_frequency * match_cost) as isize;
+
+        if saving > 0
+        {
+            segments.push((saving, first..last + SUBSTRING_LENGTH));
+        }
+    }
+    segments.sort_by_key(|(saving, _)| *saving);
+
+    let mut dictionary: Vec<u8> = segments
+        .iter()
+        .flat_map(|(_, range)| &data[range.clone()])
+        .copied()
+        .collect();
+
+    // segments overhang their slot by the tail of their last substring,
+    // drop the least useful bytes from the front
+    if dictionary.len() > size
+    {
+        dictionary.drain(..dictionary.len() - size);
+    }
+    dictionary
+}
+
+/// How much covering the substring with `hash` is worth, the number of samples it occurs in
+#[inline(always)]
+fn value(frequencies: &[u32], hash: u32) -> usize
+{
+    if hash == NO_HASH
+    {
+        return 0;
+    }
+    let frequency = frequencies[hash as usize] as usize;
+
+    if frequency >= 2
+    {
+        frequency
+    }
+    else
+    {
+        0
+    }
+}
+
+/// Start of the `SEGMENT_SIZE` window of `hashes` covering the most valuable
+/// distinct substrings.
+///
+/// `in_window` counts the substrings in the window, it is all zeroes before and after
+fn best_segment(hashes: &[u32], frequencies: &[u32], in_window: &mut [u8]) -> usize
+{
+    let mut score = 0;
+    let mut best_score = 0;
+    let mut best_start = 0;
+
+    for position in 0..hashes.len()
+    {
+        let hash = hashes[position];
+
+        if hash != NO_HASH
+        {
+            if in_window[hash as usize] == 0
+            {
+                score += value(frequencies, hash);
+            }
+            in_window[hash as usize] += 1;
+        }
+        if position >= SEGMENT_SIZE
+        {
+            remove(hashes[position - SEGMENT_SIZE], frequencies, in_window, &mut score);
+        }
+        if score > best_score
+        {
+            best_score = score;
+            best_start = (position + 1).saturating_sub(SEGMENT_SIZE);
+        }
+    }
+    for &hash in &hashes[hashes.len().saturating_sub(SEGMENT_SIZE)..]
+    {
+        remove(hash, frequencies, in_window, &mut score);
+    }
+    best_start
+}
+
+#[inline(always)]
+fn remove(hash: u32, frequencies: &[u32], in_window: &mut [u8], score: &mut usize)
+{
+    if hash != NO_HASH
+    {
+        in_window[hash as usize] -= 1;
+
+        if in_window[hash as usize] == 0
+        {
+            *score -= value(frequencies, hash);
+        }
+    }
+}
+
+#[test]
+fn trained_dictionary_compresses_unseen_samples()
+{
+    use crate::compress::{compress_to_vec_with, compress_with_dict, CompressOptions};
+    use crate::decompress::decompress_with_dict;
+    use crate::utils::pseudo_random;
+
+    // five numbers for each of the 2050 messages
+    let random = pseudo_random(4 * 5 * 2050, 0x9e37_79b9);
+    let mut numbers = random
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+    let mut next = || numbers.next().unwrap();
+    let mut message = || {
+        format!(
+            r#"{{"session": "{:08x}", "player": {{"name": "p{}", "region": "{}"}}, "event": "{}", "value": {}}}"#,
+            next(),
+            next() % 1000,
+            ["eu-west", "us-east", "ap-south"][next() % 3],
+            ["match_start", "match_end", "purchase", "login"][next() % 4],
+            next() % 100_000
+        )
+        .into_bytes()
+    };
+    let samples: Vec<Vec<u8>> = (0..2000).map(|_| message()).collect();
+    let dictionary = train_dictionary(&samples, 2048);
+
+    assert!(!dictionary.is_empty() && dictionary.len() <= 2048);
+
+    let options = CompressOptions::default();
+    let (mut plain, mut with_dict) = (0, 0);
+
+    for _ in 0..50
+    {
+        let data = message();
+        let compressed = compress_with_dict(&data, &dictionary, &options);
+
+        assert_eq!(decompress_with_dict(&compressed, &dictionary).unwrap(), data);
+        plain += compress_to_vec_with(&data, &options).len();
+        with_dict += compressed.len();
+    }
+    assert!(with_dict < plain * 2 / 3, "{with_dict} {plain}");
+}
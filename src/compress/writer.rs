@@ -0,0 +1,203 @@
+use std::io::{self, Write};
+
+use crate::checksum::Xxh32;
+use crate::compress::hash_chains::HcMatchFinder;
+use crate::compress::{end_of_stream, BlockWindow, CompressOptions, MAX_BLOCK_OUTPUT};
+use crate::constants::BLOCK_SIZE;
+use crate::frame::{FrameHeader, IndexEntry};
+
+/// A streaming compressor.
+///
+/// Bytes written are buffered into blocks of `BLOCK_SIZE`, each full block
+/// is compressed and written to the underlying writer with its 4 byte length prefix,
+/// producing the same stream as [`compress_to_vec`](crate::compress::compress_to_vec).
+///
+/// Call [`finish`](GlzWriter::finish) to compress the last partial block and
+/// get the writer back, dropping the writer also writes the last block but ignores errors.
+///
+/// # Example
+/// ```
+/// use std::io::Write;
+///
+/// let mut writer = glz::GlzWriter::new(Vec::new());
+/// writer.write_all(b"hello hello hello hello").unwrap();
+///
+/// let compressed = writer.finish().unwrap();
+/// assert_eq!(glz::decompress_to_vec(&compressed).unwrap(), b"hello hello hello hello");
+/// ```
+pub struct GlzWriter<W: Write>
+{
+    inner:    Option<W>,
+    table:    Box<HcMatchFinder>,
+    // uncompressed bytes of the current block
+    window:   BlockWindow,
+    // compressed block, with its length prefix and checksum
+    max_out:  Vec<u8>,
+    // frame header not written out yet
+    header:   Vec<u8>,
+    // frame header, until what follows the blocks is written
+    frame:    Option<FrameHeader>,
+    // checksum of the bytes accepted so far, for streams ending with one
+    checksum: Option<Xxh32>,
+    // blocks written so far, for seekable streams
+    index:    Vec<IndexEntry>,
+    // bytes of the stream written so far
+    written:  u64
+}
+
+impl<W: Write> GlzWriter<W>
+{
+    /// Create a new compressor writing to `inner` with the default options
+    pub fn new(inner: W) -> GlzWriter<W>
+    {
+        GlzWriter::with_options(inner, &CompressOptions::default())
+    }
+
+    /// Create a new compressor writing to `inner` with `options`.
+    ///
+    /// The frame header, if `options` asks for one, does not record the content size
+    pub fn with_options(inner: W, options: &CompressOptions) -> GlzWriter<W>
+    {
+        let header = options.frame_header(None, None);
+
+        GlzWriter {
+            inner:    Some(inner),
+            table:    Box::new(options.match_finder()),
+            window:   BlockWindow::new(options),
+            max_out:  vec![0; MAX_BLOCK_OUTPUT],
+            header:   header.map_or_else(Vec::new, |header| header.to_bytes()),
+            frame:    header,
+            checksum: options.content_checksum.then(|| Xxh32::new(0)),
+            index:    Vec::new(),
+            written:  0
+        }
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W
+    {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut W
+    {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Compress any buffered bytes as a final block and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W>
+    {
+        self.write_end()?;
+        self.inner.as_mut().unwrap().flush()?;
+
+        Ok(self.inner.take().unwrap())
+    }
+
+    /// Write the header, the last block and what follows the blocks, whichever are pending
+    fn write_end(&mut self) -> io::Result<()>
+    {
+        self.write_header()?;
+        self.write_block()?;
+
+        if let Some(frame) = self.frame.take()
+        {
+            let checksum = self.checksum.as_ref().map(Xxh32::finish);
+            let trailer = end_of_stream(&frame, checksum, &self.index);
+
+            self.inner.as_mut().unwrap().write_all(&trailer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the frame header, if it is still pending
+    fn write_header(&mut self) -> io::Result<()>
+    {
+        if !self.header.is_empty()
+        {
+            self.inner.as_mut().unwrap().write_all(&self.header)?;
+            self.written += self.header.len() as u64;
+            self.header.clear();
+        }
+        Ok(())
+    }
+
+    /// Compress the buffered bytes, if any, and write them out as one block
+    fn write_block(&mut self) -> io::Result<()>
+    {
+        if self.window.len() == 0
+        {
+            return Ok(());
+        }
+        self.write_header()?;
+
+        let entry = IndexEntry {
+            compressed_offset: self.written,
+            decompressed_size: self.window.len() as u32
+        };
+        let written = self.window.compress(&mut self.max_out, &mut self.table);
+
+        self.inner
+            .as_mut()
+            .unwrap()
+            .write_all(&self.max_out[..written])?;
+        self.index.push(entry);
+        self.written += written as u64;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GlzWriter<W>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        if self.window.len() == BLOCK_SIZE
+        {
+            self.write_block()?;
+        }
+        let length = self.window.fill(buf);
+
+        if let Some(checksum) = &mut self.checksum
+        {
+            checksum.update(&buf[..length]);
+        }
+        Ok(length)
+    }
+
+    /// Compress the buffered bytes as a (possibly short) block and flush the underlying writer
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for GlzWriter<W>
+{
+    fn drop(&mut self)
+    {
+        if self.inner.is_some()
+        {
+            let _ = self.write_end();
+        }
+    }
+}
+
+#[test]
+fn writer_matches_compress_to_vec()
+{
+    let data = b"The quick brown fox jumps over the lazy dog. ".repeat(15000);
+
+    let mut writer = GlzWriter::new(Vec::new());
+
+    for chunk in data.chunks(1000 + 7)
+    {
+        writer.write_all(chunk).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+
+    assert_eq!(compressed, crate::compress::compress_to_vec(&data));
+}
@@ -19,3 +19,14 @@ pub const MEM_SIZE: usize = 16 * (1 << 20);
 pub const TOKEN: usize = 7;
 
 pub const BLOCK_SIZE: usize = 1 << 18; //1 * (1 << 20);
+/// How far matches in linked blocks may reach back into the blocks before them
+pub const HISTORY_SIZE: usize = BLOCK_SIZE;
+/// Set in a block's length prefix when its matches may reach into earlier blocks
+pub const BLOCK_LINKED: u32 = 1 << 30;
+/// Set in the length prefix of a dictionary record, which holds the ID
+/// of the dictionary the blocks after it reach into instead of a block
+pub const BLOCK_DICTIONARY: u32 = 1 << 29;
+/// Set in the length prefix of a stored block, whose bytes are the uncompressed data
+pub const BLOCK_STORED: u32 = 1 << 31;
+/// Bits of a block's length prefix holding its compressed length
+pub const BLOCK_SIZE_MASK: u32 = BLOCK_DICTIONARY - 1;
@@ -1,14 +1,43 @@
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
-use std::time::Instant;
 
-use crate::constants::{GLZ_MIN_MATCH, LITERAL_BITS, MEM_SIZE, ML_BITS, OFFSET_BIT, SLOP_BYTES};
-use crate::utils::{const_copy, fixed_copy_within};
+use crate::checksum::{dictionary_id, xxh32};
+use crate::constants::{
+    GLZ_MIN_MATCH, HISTORY_SIZE, LITERAL_BITS, MEM_SIZE, ML_BITS, OFFSET_BIT, SLOP_BYTES
+};
+use crate::errors::GlzError;
+use crate::decompress::parallel::{decode_parallel, decode_parallel_trusted};
+use crate::decompress::sequences::read_sequence;
+use crate::frame::{
+    check_block_checksum, check_content_checksum, read_checksum, FrameHeader, END_MARK
+};
+use crate::utils::{const_copy, fixed_copy_within, BlockPrefix};
+
+mod list;
+mod parallel;
+mod reader;
+mod seekable;
+mod sequences;
+mod verify;
+
+pub use crate::decompress::list::{list_blocks, BlockInfo, StreamInfo};
+pub use crate::decompress::reader::GlzReader;
+pub use crate::decompress::seekable::SeekableReader;
+pub use crate::decompress::sequences::{Sequence, Sequences};
+pub use crate::decompress::verify::{verify, verify_file};
 
 const TOKEN_LITERAL: usize = 32;
 const TOKEN_MATCH_LENGTH: usize = 32;
+/// Longest `encode_mod` value accepted by the checked decoder,
+/// enough to hold any offset or length inside a `MEM_SIZE` block
+pub const MAX_ENCODE_MOD_BYTES: usize = 5;
 
+/// Returns the decoded value and the number of bytes consumed from `encode_mod`.
+///
+/// # Panics
+/// If `input` ends before the value does, i.e. holds no byte less than or equal to `0x7f`.
+/// Use [`decode_encode_mod_checked`] for untrusted input.
 #[inline(always)]
 pub fn decode_encode_mod(input: &[u8]) -> (usize, usize)
 {
@@ -18,10 +47,37 @@ pub fn decode_encode_mod(input: &[u8]) -> (usize, usize)
     let mut value: usize = 0;
     loop
     {
-        // SAFETY:  None :)
+        let a = input[curr_position];
+        c_var = usize::from(a) << (u_var & 0x1f);
+        value += c_var;
+        curr_position += 1;
+
+        if a <= 0x7f
+        {
+            break;
+        }
+        u_var += 7;
+    }
+    return (value, curr_position);
+}
+
+/// [`decode_encode_mod`] without the bounds checks, for the fast path.
+///
+/// # Safety
+/// `input` must contain a terminated `encode_mod` value, otherwise this reads past the slice
+#[inline(always)]
+unsafe fn decode_encode_mod_unchecked(input: &[u8]) -> (usize, usize)
+{
+    let mut curr_position = 0;
+    let mut u_var = 0;
+    let mut c_var;
+    let mut value: usize = 0;
+    loop
+    {
+        // SAFETY: Upheld by the caller
         let a = *unsafe { input.get_unchecked(curr_position) };
         c_var = usize::from(a) << (u_var & 0x1f);
-        value = value + c_var;
+        value += c_var;
         curr_position += 1;
 
         if a <= 0x7f
@@ -33,12 +89,71 @@ pub fn decode_encode_mod(input: &[u8]) -> (usize, usize)
     return (value, curr_position);
 }
 
-pub fn decode_sequences(
+/// Bounds checked version of [`decode_encode_mod`].
+///
+/// Returns `None` if the value runs past the end of `input`, is longer than
+/// [`MAX_ENCODE_MOD_BYTES`] or is larger than `MEM_SIZE`, which no offset
+/// or length inside a block can be
+#[inline(always)]
+pub fn decode_encode_mod_checked(input: &[u8]) -> Option<(usize, usize)>
+{
+    // 5 bytes of 7 bits fit in a u64 even where usize is 32 bits
+    let mut value: u64 = 0;
+
+    for (curr_position, a) in input.iter().take(MAX_ENCODE_MOD_BYTES).enumerate()
+    {
+        value += u64::from(*a) << (7 * curr_position);
+
+        if *a <= 0x7f
+        {
+            let value = usize::try_from(value).ok().filter(|&value| value <= MEM_SIZE)?;
+
+            return Some((value, curr_position + 1));
+        }
+    }
+    None
+}
+
+/// Decode a single block of `output_size` compressed bytes from `input` into `output`,
+/// returning the number of bytes decoded.
+///
+/// This is the fast path, it copies literals and matches in fixed size chunks
+/// that overshoot their real lengths and does not check most lengths against the buffers.
+///
+/// Errors are reported relative to the start of the block, with a block index of zero.
+///
+/// # Safety
+/// - The block must come from a trusted source, such as a stream this crate
+///   wrote. Malformed input can read or write out of bounds.
+/// - `input` must have at least [`SLOP_BYTES`] readable bytes after `output_size`.
+/// - `output` must have room for the decoded block plus [`SLOP_BYTES`],
+///   blocks that would decode into the last [`SLOP_BYTES`] fail with
+///   [`GlzError::OutputOverflow`].
+///
+/// Use [`decode_sequences_safe`] for untrusted input.
+pub unsafe fn decode_sequences(
     input: &[u8], output_size: usize, output: &mut [u8]
-) -> Result<usize, &'static str>
+) -> Result<usize, GlzError>
+{
+    decode_sequences_at(input, output_size, output, 0)
+}
+
+/// Like [`decode_sequences`], but decodes the block into `output[start..]`,
+/// letting its matches reach back into the history in `output[..start]`.
+///
+/// This is how blocks of a linked stream are decoded.
+///
+/// # Safety
+/// Same as [`decode_sequences`], with `output` having room for `start`
+/// plus the decoded block plus [`SLOP_BYTES`]
+pub unsafe fn decode_sequences_at(
+    input: &[u8], output_size: usize, output: &mut [u8], start: usize
+) -> Result<usize, GlzError>
 {
     let mut input_offset = 0;
-    let mut output_offset = 0;
+    let mut output_offset = start;
+    // the chunked copies overshoot into the slack bytes at the end of `output`
+    let output_end = output.len().saturating_sub(SLOP_BYTES);
 
     loop
     {
@@ -46,6 +161,7 @@ pub fn decode_sequences(
         {
             break;
         }
+        let sequence_start = input_offset;
         // read the next token
         let token = unsafe { *input.get_unchecked(input_offset) };
 
@@ -60,9 +176,17 @@ pub fn decode_sequences(
         if literal_length == 7
         {
             // too long of a literal, decode using EncodeMod
-            let (ll, b) = decode_encode_mod(&input[input_offset..]);
-            input_offset += usize::from(b);
-            literal_length += ll as usize;
+            let (ll, b) = decode_encode_mod_unchecked(&input[input_offset..]);
+            input_offset += b;
+            literal_length += ll;
+
+            if output_offset + literal_length > output_end
+            {
+                return Err(GlzError::OutputOverflow {
+                    block:  0,
+                    offset: sequence_start
+                });
+            }
 
             // unchecked copy of a literal, helps in copying literals greater than 7
             // but less than 16
@@ -112,32 +236,48 @@ pub fn decode_sequences(
         }
         if input_offset > output_size
         {
-            return Err("Corrupt file");
+            return Err(GlzError::LiteralOverflow {
+                block:  0,
+                offset: sequence_start,
+                length: literal_length
+            });
         }
 
         // extract match offset
-        let (ol, consumed_offset) = decode_encode_mod(&input[input_offset..]);
+        let (ol, consumed_offset) = decode_encode_mod_unchecked(&input[input_offset..]);
 
-        offset |= (ol << 2) as usize;
-
-        let match_start = output_offset - offset;
+        offset |= ol << 2;
 
         if offset > output_offset
         {
-            return Err("Corrupt file");
+            return Err(GlzError::InvalidOffset {
+                block:    0,
+                offset:   sequence_start,
+                distance: offset,
+                position: output_offset
+            });
         }
 
+        let match_start = output_offset - offset;
+
         //dbg!(literal_length, match_length, match_start, output_offset);
 
         // increment the input to point to match
-        input_offset += usize::from(consumed_offset);
+        input_offset += consumed_offset;
         // extract the match length
         if match_length == (7 + GLZ_MIN_MATCH)
         {
             // too long of a match, decode using EncodeMod
-            let (ml, b) = decode_encode_mod(&input[input_offset..]);
-            input_offset += usize::from(b);
-            match_length += ml as usize;
+            let (ml, b) = decode_encode_mod_unchecked(&input[input_offset..]);
+            input_offset += b;
+            match_length += ml;
+        }
+        if output_offset + match_length > output_end
+        {
+            return Err(GlzError::OutputOverflow {
+                block:  0,
+                offset: sequence_start
+            });
         }
 
         // copy the match
@@ -205,58 +345,829 @@ pub fn decode_sequences(
         output_offset += match_length;
     }
 
-    return Ok(output_offset);
+    return Ok(output_offset - start);
+}
+
+/// Decode a single block from `input` into `output`, returning the number of bytes decoded.
+///
+/// Unlike [`decode_sequences`], every length, offset and `encode_mod` value is checked
+/// against the buffers, so no input, however malformed can make this read or write
+/// out of bounds. `input` is exactly the compressed block and neither buffer needs
+/// extra slack bytes.
+///
+/// Errors are reported relative to the start of the block, with a block index of zero.
+pub fn decode_sequences_safe(input: &[u8], output: &mut [u8]) -> Result<usize, GlzError>
+{
+    decode_sequences_safe_at(input, output, 0)
+}
+
+/// Like [`decode_sequences_safe`], but decodes the block into `output[start..]`,
+/// letting its matches reach back into the history in `output[..start]`.
+///
+/// This is how blocks of a linked stream are decoded.
+pub fn decode_sequences_safe_at(
+    input: &[u8], output: &mut [u8], start: usize
+) -> Result<usize, GlzError>
+{
+    let mut input_offset = 0;
+    let mut output_offset: usize = start;
+
+    while input_offset < input.len()
+    {
+        let sequence = read_sequence(input, input_offset)?;
+        let literal_length = sequence.ll;
+        let match_length = sequence.ml;
+        let offset = sequence.ol;
+
+        // copy literals
+        output
+            .get_mut(output_offset..output_offset.saturating_add(literal_length))
+            .ok_or(GlzError::OutputOverflow {
+                block:  0,
+                offset: sequence.start
+            })?
+            .copy_from_slice(&input[sequence.literals..sequence.literals + literal_length]);
+
+        input_offset += sequence.cost;
+        output_offset += literal_length;
+
+        if match_length == 0
+        {
+            break;
+        }
+        if offset == 0 || offset > output_offset
+        {
+            return Err(GlzError::InvalidOffset {
+                block:    0,
+                offset:   sequence.start,
+                distance: offset,
+                position: output_offset
+            });
+        }
+        if match_length > output.len() - output_offset
+        {
+            return Err(GlzError::OutputOverflow {
+                block:  0,
+                offset: sequence.start
+            });
+        }
+        // copy the match
+        let match_start = output_offset - offset;
+
+        if offset >= match_length
+        {
+            output.copy_within(match_start..match_start + match_length, output_offset);
+        }
+        else
+        {
+            // overlapping match, the source is repeated every `offset` bytes
+            for i in 0..match_length
+            {
+                output[output_offset + i] = output[match_start + i];
+            }
+        }
+        output_offset += match_length;
+    }
+
+    return Ok(output_offset - start);
+}
+
+/// Output buffer of the decompressors.
+///
+/// Holds the last decoded block, preceded for linked blocks by up to `HISTORY_SIZE` bytes
+/// of the blocks before it or of the dictionary to reach into,
+/// and followed by slack bytes for `decode_sequences`.
+///
+/// The buffer starts empty and grows to fit the blocks decoded into it,
+/// so small streams don't pay for room a `MEM_SIZE` block would need.
+pub(crate) struct OutputWindow
+{
+    buffer:   Vec<u8>,
+    // bytes of history before the block
+    history:  usize,
+    // bytes in the block
+    length:   usize,
+    // bytes a block may decode to without growing the buffer
+    capacity: usize
+}
+
+impl OutputWindow
+{
+    pub fn new() -> OutputWindow
+    {
+        OutputWindow {
+            buffer:   Vec::new(),
+            history:  0,
+            length:   0,
+            capacity: 0
+        }
+    }
+
+    /// Make room for the history and a block of `capacity` decoded bytes
+    fn reserve(&mut self, capacity: usize)
+    {
+        self.capacity = self.capacity.max(capacity.min(MEM_SIZE));
+
+        let size = self.history + self.capacity + SLOP_BYTES;
+
+        if self.buffer.len() < size
+        {
+            self.buffer.resize(size, 0);
+        }
+    }
+
+    /// The last decoded block
+    pub fn block(&self) -> &[u8]
+    {
+        &self.buffer[self.history..self.history + self.length]
+    }
+
+    /// Check the dictionary record `record` against `dictionary` and make
+    /// the dictionary the history of the next block.
+    ///
+    /// Errors are reported relative to the start of the record, with a block index of zero.
+    pub fn load_dictionary(
+        &mut self, record: &[u8], dictionary: Option<&[u8]>
+    ) -> Result<(), GlzError>
+    {
+        let expected = record.get(..4).ok_or(GlzError::Truncated {
+            block:  0,
+            offset: record.len()
+        })?;
+        let expected = u32::from_le_bytes(expected.try_into().unwrap());
+        let found = dictionary.map(dictionary_id);
+
+        let dictionary = match dictionary
+        {
+            Some(dictionary) if found == Some(expected) => dictionary,
+            _ =>
+            {
+                return Err(GlzError::DictionaryMismatch {
+                    block: 0,
+                    offset: 0,
+                    expected: Some(expected),
+                    found
+                })
+            }
+        };
+        let keep = dictionary.len().min(HISTORY_SIZE);
+
+        self.history = keep;
+        self.length = 0;
+        self.reserve(0);
+        self.buffer[..keep].copy_from_slice(&dictionary[dictionary.len() - keep..]);
+
+        Ok(())
+    }
+
+    /// Decode the block `input` described by `prefix` with [`decode_sequences_safe_at`],
+    /// or copy it if it is stored, replacing the last one.
+    ///
+    /// Errors are reported relative to the start of the block, with a block index of zero.
+    pub fn decode(&mut self, input: &[u8], prefix: BlockPrefix) -> Result<&[u8], GlzError>
+    {
+        // SAFETY: The checked decoder has no requirements
+        unsafe { self.decode_with::<true>(input, prefix) }
+    }
+
+    /// Like [`decode`](Self::decode), using the fast [`decode_sequences_at`].
+    ///
+    /// # Safety
+    /// The block must come from a trusted source and `input` must be followed
+    /// by [`SLOP_BYTES`] readable bytes, see [`decode_sequences`]
+    pub unsafe fn decode_trusted(
+        &mut self, input: &[u8], prefix: BlockPrefix
+    ) -> Result<&[u8], GlzError>
+    {
+        self.decode_with::<false>(input, prefix)
+    }
+
+    unsafe fn decode_with<const SAFE: bool>(
+        &mut self, input: &[u8], prefix: BlockPrefix
+    ) -> Result<&[u8], GlzError>
+    {
+        // the last block joins the history of a linked one
+        let end = self.history + self.length;
+        let keep = if prefix.linked { end.min(HISTORY_SIZE) } else { 0 };
+
+        self.buffer.copy_within(end - keep..end, 0);
+        self.history = keep;
+        self.length = 0;
+
+        if prefix.stored
+        {
+            // the block is its own decoded bytes, callers check it fits in MEM_SIZE
+            let block = &input[..prefix.size];
+
+            self.reserve(block.len());
+            self.buffer[self.history..self.history + block.len()].copy_from_slice(block);
+            self.length = block.len();
+
+            return Ok(self.block());
+        }
+        // most blocks shrink to no less than a quarter, the others grow the buffer
+        self.reserve(prefix.size.saturating_mul(4));
+
+        loop
+        {
+            // an independent block gets an output starting after the history
+            // the fast path is given the slack bytes as part of its output
+            let end = if SAFE { self.capacity } else { self.capacity + SLOP_BYTES };
+            let (output, start) = if prefix.linked
+            {
+                (&mut self.buffer[..self.history + end], self.history)
+            }
+            else
+            {
+                (&mut self.buffer[self.history..self.history + end], 0)
+            };
+            let decoded = if SAFE
+            {
+                decode_sequences_safe_at(&input[..prefix.size], output, start)
+            }
+            else
+            {
+                // SAFETY: Upheld by the caller of decode_trusted
+                unsafe { decode_sequences_at(input, prefix.size, output, start) }
+            };
+            match decoded
+            {
+                Err(GlzError::OutputOverflow { .. }) if self.capacity < MEM_SIZE =>
+                {
+                    self.reserve(self.capacity.max(1 << 10) * 2);
+                }
+                decoded =>
+                {
+                    self.length = decoded?;
+                    return Ok(self.block());
+                }
+            }
+        }
+    }
+}
+
+/// Decompress a stream produced by [`compress_to_vec`](crate::compress::compress_to_vec)
+/// or the command line tool into a new vector.
+///
+/// Every block is decoded with [`decode_sequences_safe`], so this is safe
+/// to call on untrusted input.
+pub fn decompress_to_vec(input: &[u8]) -> Result<Vec<u8>, GlzError>
+{
+    decompress_blocks::<true>(input, None)
+}
+
+/// Decompress a stream into a new vector using the fast [`decode_sequences`] path.
+///
+/// # Safety
+/// `input` must come from a trusted source, such as a stream this crate wrote,
+/// see [`decode_sequences`]
+pub unsafe fn decompress_to_vec_trusted(input: &[u8]) -> Result<Vec<u8>, GlzError>
+{
+    decompress_blocks::<false>(input, None)
+}
+
+/// Decompress a stream into a new vector, decoding up to `threads` blocks at once.
+///
+/// Blocks are decoded with [`decode_sequences_safe`], so this is safe to call on
+/// untrusted input. Streams with linked blocks are decoded on the calling thread.
+pub fn decompress_to_vec_parallel(input: &[u8], threads: usize) -> Result<Vec<u8>, GlzError>
+{
+    let mut out = Vec::with_capacity(input.len() * 2);
+
+    decode_parallel(input, threads, |_, block| {
+        out.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+/// Like [`decompress_to_vec_parallel`], using the fast [`decode_sequences`] path.
+///
+/// # Safety
+/// `input` must come from a trusted source, such as a stream this crate wrote,
+/// see [`decode_sequences`]
+pub unsafe fn decompress_to_vec_parallel_trusted(
+    input: &[u8], threads: usize
+) -> Result<Vec<u8>, GlzError>
+{
+    let mut out = Vec::with_capacity(input.len() * 2);
+
+    decode_parallel_trusted(input, threads, |_, block| {
+        out.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+/// Decompress a stream produced by [`compress_with_dict`](crate::compress::compress_with_dict)
+/// into a new vector.
+///
+/// Fails with [`GlzError::DictionaryMismatch`] unless `dictionary` is the one the
+/// stream was compressed with. Like [`decompress_to_vec`], this is safe to call on
+/// untrusted input.
+pub fn decompress_with_dict(input: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, GlzError>
+{
+    // the dictionary is named by the frame header, or by a record before the first block
+    let (header, start) = split_header(input)?;
+    let first = input
+        .get(start..start + 4)
+        .map(|bytes| BlockPrefix::from_bytes(bytes.try_into().unwrap()));
+
+    let named = header.and_then(|header| header.dictionary_id).is_some()
+        || first.is_some_and(|prefix| prefix.dictionary);
+
+    if !named
+    {
+        return Err(GlzError::DictionaryMismatch {
+            block:    0,
+            offset:   0,
+            expected: None,
+            found:    Some(dictionary_id(dictionary))
+        });
+    }
+    decompress_blocks::<true>(input, Some(dictionary))
 }
 
-pub fn decompress(input_file: String, output_file: String)
+fn decompress_blocks<const SAFE: bool>(
+    input: &[u8], dictionary: Option<&[u8]>
+) -> Result<Vec<u8>, GlzError>
 {
-    let p = Path::new(&input_file);
+    // decode_sequences reads and writes past the end of a block,
+    // so both sides go through buffers with slack bytes
+    let mut max_in = Vec::new();
+    let mut window = OutputWindow::new();
+    let mut out = Vec::with_capacity(input.len() * 2);
+
+    let (header, mut position) = split_header(input)?;
+    let mut block_index = 0;
+    let block_checksums = header.is_some_and(|header| header.block_checksums);
+    let content_checksum = header.is_some_and(|header| header.content_checksum);
+    let end_mark = header.is_some_and(|header| header.has_end_mark());
+    let mut ended = false;
+
+    if let Some(id) = header.and_then(|header| header.dictionary_id)
+    {
+        window.load_dictionary(&id.to_le_bytes(), dictionary)?;
+    }
+
+    while position < input.len()
+    {
+        let truncated = GlzError::Truncated {
+            block:  block_index,
+            offset: position
+        };
+
+        let prefix_bytes = input.get(position..position + 4).ok_or(truncated)?;
+
+        if end_mark && prefix_bytes == END_MARK
+        {
+            // the content checksum and block index are not blocks
+            if content_checksum
+            {
+                let expected = read_checksum(input, position + 4, block_index)?;
+
+                check_content_checksum(xxh32(&out, 0), expected, block_index, position + 4)?;
+            }
+            ended = true;
+            break;
+        }
+        let prefix = BlockPrefix::from_bytes(prefix_bytes.try_into().unwrap());
+        let size = prefix.size;
+
+        if size > MEM_SIZE
+        {
+            return Err(GlzError::BlockTooLarge {
+                block: block_index,
+                offset: position,
+                size
+            });
+        }
+        position += 4;
 
-    let p_len = p.metadata().unwrap().len() as usize;
-    // allocate and add slack bytes, so that we don't panic in simd_decode
-    let mut max_in = Vec::with_capacity(MEM_SIZE + SLOP_BYTES);
-    max_in.resize(MEM_SIZE, 0);
+        let block = input
+            .get(position..position + size)
+            .ok_or(GlzError::Truncated {
+                block:  block_index,
+                offset: input.len()
+            })?;
 
-    let mut max_out = Vec::with_capacity(MEM_SIZE + SLOP_BYTES);
-    max_out.resize(MEM_SIZE, 0);
+        if prefix.dictionary
+        {
+            window
+                .load_dictionary(block, dictionary)
+                .map_err(|e| e.at_block(block_index, position))?;
+            position += size;
+            continue;
+        }
+        let decoded = if SAFE
+        {
+            window.decode(block, prefix)
+        }
+        else
+        {
+            max_in.clear();
+            max_in.extend_from_slice(block);
+            max_in.resize(size + SLOP_BYTES, 0);
+            // SAFETY: The caller of decompress_to_vec_trusted vouches for the input,
+            // and it is followed by slack bytes
+            unsafe { window.decode_trusted(&max_in, prefix) }
+        };
+        let decoded = decoded.map_err(|e| e.at_block(block_index, position))?;
+
+        position += size;
+
+        if block_checksums
+        {
+            let expected = read_checksum(input, position, block_index)?;
 
-    let start = Instant::now();
-    let mut fd = File::open(p).unwrap();
-    let mut out_fd = OpenOptions::new()
+            check_block_checksum(decoded, expected, block_index, position)?;
+            position += 4;
+        }
+        out.extend_from_slice(decoded);
+        block_index += 1;
+    }
+    if end_mark && !ended
+    {
+        // the stream stopped before its end mark
+        return Err(GlzError::Truncated {
+            block:  block_index,
+            offset: input.len()
+        });
+    }
+    if let Some(header) = header
+    {
+        header.check_content_size(out.len() as u64, block_index, input.len())?;
+    }
+    Ok(out)
+}
+
+/// The frame header at the start of `input`, if it has one,
+/// and the offset of the first block
+pub(crate) fn split_header(input: &[u8]) -> Result<(Option<FrameHeader>, usize), GlzError>
+{
+    Ok(match FrameHeader::parse(input)?
+    {
+        Some((header, size)) => (Some(header), size),
+        None => (None, 0)
+    })
+}
+
+/// Decompress `input_file` into `output_file`, decoding up to `threads` blocks at once,
+/// returning the number of bytes read and written.
+///
+/// See [`decompress_file`]
+pub fn decompress(
+    input_file: String, output_file: String, threads: usize
+) -> Result<(u64, u64), GlzError>
+{
+    let out_fd = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
         .open(&output_file)
-        .unwrap();
+        .map_err(|error| GlzError::Io {
+            block: 0,
+            offset: 0,
+            error
+        })?;
+
+    decompress_file(input_file, out_fd, threads)
+}
+
+/// Decompress `input_file` into `output`, decoding up to `threads` blocks at once,
+/// returning the number of bytes read and written.
+///
+/// The input is memory mapped, see [`decompress_to_vec_parallel`]. Use
+/// [`decompress_stream`] for inputs that can not be mapped, such as pipes.
+pub fn decompress_file<W: Write>(
+    input_file: impl AsRef<Path>, output: W, threads: usize
+) -> Result<(u64, u64), GlzError>
+{
+    // SAFETY: The checked decoder has no requirements
+    unsafe { decompress_file_with::<true, W>(input_file.as_ref(), output, threads) }
+}
+
+/// Like [`decompress_file`], using the fast [`decode_sequences`] path.
+///
+/// # Safety
+/// `input_file` must come from a trusted source, such as a file this crate wrote,
+/// and must not change while it is decompressed, see [`decode_sequences`]
+pub unsafe fn decompress_file_trusted<W: Write>(
+    input_file: impl AsRef<Path>, output: W, threads: usize
+) -> Result<(u64, u64), GlzError>
+{
+    decompress_file_with::<false, W>(input_file.as_ref(), output, threads)
+}
+
+unsafe fn decompress_file_with<const SAFE: bool, W: Write>(
+    input_file: &Path, mut output: W, threads: usize
+) -> Result<(u64, u64), GlzError>
+{
+    let io_error = |block, offset| {
+        move |error| GlzError::Io {
+            block,
+            offset,
+            error
+        }
+    };
+
+    let fd = File::open(input_file).map_err(io_error(0, 0))?;
+    // SAFETY: The file must not be changed while mapped, the checked decoder
+    // keeps a change from going out of bounds but not from tearing the output,
+    // callers of decompress_file_trusted vouch that it does not change
+    let input = unsafe { memmap2::Mmap::map(&fd) }.map_err(io_error(0, 0))?;
 
-    let mut curr_len = 0;
-    let mut file_contents = [0; 4];
     let mut end_position = 0;
+    let write = |block_index, block: &[u8]| {
+        output
+            .write_all(block)
+            .map_err(io_error(block_index, end_position))?;
+        end_position += block.len();
+        Ok(())
+    };
+    if SAFE
+    {
+        decode_parallel(&input, threads, write)?;
+    }
+    else
+    {
+        // SAFETY: Upheld by the caller of decompress_file_trusted
+        unsafe { decode_parallel_trusted(&input, threads, write)? };
+    }
+    output.flush().map_err(io_error(0, end_position))?;
+
+    Ok((input.len() as u64, end_position as u64))
+}
 
-    while curr_len < p_len
+/// Decompress the stream read from `input` into `output`,
+/// returning the number of bytes read and written.
+///
+/// `input` is only read from, so it can be a pipe. Blocks are decoded one at a time,
+/// as by a [`GlzReader`], and reading stops at the end of the stream.
+pub fn decompress_stream<R: Read, W: Write>(
+    input: R, mut output: W
+) -> Result<(u64, u64), GlzError>
+{
+    let mut reader = GlzReader::new(input);
+    let mut written = 0;
+
+    while let Some(block) = reader.next_decoded()?
     {
-        fd.read_exact(&mut file_contents).unwrap();
-        let size = u32::from_le_bytes(file_contents[0..4].try_into().unwrap()) as usize;
-        fd.read_exact(&mut max_in[0..size]).unwrap();
+        output.write_all(block).map_err(|error| GlzError::Io {
+            block: 0,
+            offset: written,
+            error
+        })?;
+        written += block.len();
+    }
+    output.flush().map_err(|error| GlzError::Io {
+        block: 0,
+        offset: written,
+        error
+    })?;
+
+    Ok((reader.compressed_position() as u64, written as u64))
+}
+
+#[test]
+fn decompress_truncated_stream()
+{
+    let compressed = crate::compress::compress_to_vec(&b"abcdefgh".repeat(100));
+
+    let err = decompress_to_vec(&compressed[..compressed.len() - 1]).unwrap_err();
+    assert!(matches!(err, GlzError::Truncated { block: 0, .. }));
+}
+
+#[test]
+fn decode_safe_rejects_malformed_blocks()
+{
+    let mut out = [0; 64];
+    // literal length continues past the end of the input
+    assert!(matches!(
+        decode_sequences_safe(&[0x07, 0xff], &mut out),
+        Err(GlzError::Truncated { .. })
+    ));
+    // two literals promised, one present
+    assert!(matches!(
+        decode_sequences_safe(&[0x02, b'a'], &mut out),
+        Err(GlzError::LiteralOverflow { .. })
+    ));
+    // one literal followed by a match with an offset of zero
+    assert!(matches!(
+        decode_sequences_safe(&[0x01, b'a', 0x00], &mut out),
+        Err(GlzError::InvalidOffset { .. })
+    ));
+    // a match longer than the output buffer
+    assert!(matches!(
+        decode_sequences_safe(&[0x79, b'a', 0x00, 0x7f], &mut out),
+        Err(GlzError::OutputOverflow { .. })
+    ));
+    // a literal length no block can hold, which would overflow a 32 bit usize
+    assert!(matches!(
+        decode_sequences_safe(&[0x07, 0xff, 0xff, 0xff, 0xff, 0x7f], &mut out),
+        Err(GlzError::Truncated { .. })
+    ));
+    assert_eq!(decode_encode_mod_checked(&[0xff, 0xff, 0xff, 0xff, 0x7f]), None);
+
+    let (mut encoded, mut length) = ([0; 8], 0);
+    crate::utils::compress_encode_mod(MEM_SIZE, &mut encoded, &mut length);
+    assert_eq!(decode_encode_mod_checked(&encoded), Some((MEM_SIZE, length)));
+}
+
+#[test]
+fn decompress_framed_streams()
+{
+    use std::io::{Read, Write};
+
+    use crate::compress::{compress_to_vec_with, compress_with_dict, CompressOptions, GlzWriter};
+    use crate::frame::FRAME_MAGIC;
+
+    let data = b"Framed streams say what they are and how long they are. ".repeat(10_000);
+    let options = CompressOptions {
+        header: true,
+        ..Default::default()
+    };
+    let framed = compress_to_vec_with(&data, &options);
+    assert!(framed.starts_with(&FRAME_MAGIC));
+
+    let mut writer = GlzWriter::with_options(Vec::new(), &options);
+    writer.write_all(&data).unwrap();
+    let written = writer.finish().unwrap();
+
+    for stream in [&framed, &written]
+    {
+        assert!(decompress_to_vec(stream).unwrap() == data);
+        assert!(unsafe { decompress_to_vec_trusted(stream) }.unwrap() == data);
+        assert!(decompress_to_vec_parallel(stream, 2).unwrap() == data);
+
+        let mut out = Vec::new();
+        GlzReader::new(&stream[..]).read_to_end(&mut out).unwrap();
+        assert!(out == data);
+    }
+
+    // the dictionary ID moves to the header
+    let dictionary = &data[..1000];
+    let with_dict = compress_with_dict(&data[..200], dictionary, &options);
+    assert_eq!(decompress_with_dict(&with_dict, dictionary).unwrap(), &data[..200]);
+    assert!(matches!(
+        decompress_to_vec(&with_dict),
+        Err(GlzError::DictionaryMismatch { found: None, .. })
+    ));
+
+    // blocks appended after the stream break the recorded content size
+    let mut longer = framed.clone();
+    longer.extend_from_slice(&crate::compress::compress_to_vec(b"one more block"));
+
+    assert!(matches!(
+        decompress_to_vec(&longer),
+        Err(GlzError::ContentSizeMismatch { .. })
+    ));
+    assert!(matches!(
+        decompress_to_vec_parallel(&longer, 2),
+        Err(GlzError::ContentSizeMismatch { .. })
+    ));
+    let err = GlzReader::new(&longer[..]).read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn checksums_detect_corruption()
+{
+    use std::io::Read;
+
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::constants::BLOCK_SIZE;
+    use crate::utils::pseudo_random;
+
+    // incompressible, so the middle of every block is literals
+    let data = pseudo_random(3 * BLOCK_SIZE, 0x2545_f491);
 
-        match decode_sequences(&max_in, size as usize, &mut max_out)
+    let decoders = |stream: &[u8]| {
+        let mut out = Vec::new();
+        let reader = GlzReader::new(stream)
+            .read_to_end(&mut out)
+            .map_err(|e| e.into_inner().unwrap().downcast::<GlzError>().unwrap());
+
+        [
+            decompress_to_vec(stream).map(|_| ()),
+            decompress_to_vec_parallel(stream, 2).map(|_| ()),
+            reader.map(|_| ()).map_err(|e| *e)
+        ]
+    };
+
+    for (block_checksums, content_checksum) in [(true, false), (false, true), (true, true)]
+    {
+        let options = CompressOptions {
+            block_checksums,
+            content_checksum,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+
+        assert!(decompress_to_vec(&compressed).unwrap() == data);
+        assert!(decompress_to_vec_parallel(&compressed, 2).unwrap() == data);
+
+        // flip a literal in the middle of block 1
+        let (_, mut position) = split_header(&compressed).unwrap();
+        let first = BlockPrefix::from_bytes(compressed[position..][..4].try_into().unwrap());
+        position += 4 + first.size + if block_checksums { 4 } else { 0 };
+
+        let mut corrupt = compressed.clone();
+        corrupt[position + 4 + BLOCK_SIZE / 2] ^= 0x10;
+
+        for result in decoders(&corrupt)
         {
-            Ok(f_length) =>
+            match result.unwrap_err()
             {
-                curr_len += size as usize + 4 /*size bytes*/;
-                end_position += f_length;
-                out_fd.write_all(&max_out[..f_length]).unwrap();
-                out_fd.flush().unwrap();
+                GlzError::BlockChecksumMismatch { block, .. } => assert_eq!(block, 1),
+                GlzError::ContentChecksumMismatch { .. } => assert!(!block_checksums),
+                e => panic!("{e}")
             }
-            Err(str) =>
+        }
+        if content_checksum
+        {
+            // a stream cut at a block boundary is missing its content checksum
+            for result in decoders(&compressed[..position])
             {
-                println!("{}", str);
-                return;
+                assert!(matches!(result, Err(GlzError::Truncated { .. })));
             }
         }
     }
+}
+
+#[test]
+fn streams_round_trip_through_pipes()
+{
+    use crate::compress::{compress_stream, compress_to_vec_with, CompressOptions};
+
+    /// A reader handing out a few bytes at a time, like a pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_>
+    {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+        {
+            let length = buf.len().min(self.0.len()).min(4093);
+
+            buf[..length].copy_from_slice(&self.0[..length]);
+            self.0 = &self.0[length..];
+            Ok(length)
+        }
+    }
+
+    let data = b"Pipes have no length, they just end. ".repeat(30_000);
+    let options = CompressOptions {
+        linked: true,
+        block_checksums: true,
+        content_checksum: true,
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    let (read, written) = compress_stream(Trickle(&data), &mut compressed, &options, None).unwrap();
+
+    assert_eq!((read, written), (data.len() as u64, compressed.len() as u64));
+    // the same stream, but the header does not record the content size
+    assert!(compressed.len() < compress_to_vec_with(&data, &options).len());
+
+    let mut out = Vec::new();
+    let (read, written) = decompress_stream(Trickle(&compressed), &mut out).unwrap();
+
+    assert!(out == data);
+    assert_eq!((read, written), (compressed.len() as u64, data.len() as u64));
+}
+
+#[test]
+fn output_window_grows_to_fit_blocks()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::constants::BLOCK_SIZE;
 
-    let end = Instant::now();
+    // a short stream only needs room for its one block
+    let compressed = crate::compress::compress_to_vec(&b"small".repeat(40));
+    let mut window = OutputWindow::new();
 
-    println!("{curr_len}->{end_position} in {:?}", end - start)
+    for block in list_blocks(&compressed).unwrap().blocks
+    {
+        let prefix = compressed[block.offset..block.offset + 4].try_into().unwrap();
+        let input = &compressed[block.offset + 4..block.offset + 4 + block.compressed_size];
+
+        window.decode(input, BlockPrefix::from_bytes(prefix)).unwrap();
+    }
+    assert_eq!(window.block(), b"small".repeat(40));
+    assert!(window.buffer.len() < 2 * SLOP_BYTES);
+
+    // blocks of zeros shrink to far less than a quarter and grow the window as they decode
+    let data = vec![0; 3 * BLOCK_SIZE];
+
+    for linked in [false, true]
+    {
+        let options = CompressOptions {
+            linked,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+
+        assert!(decompress_to_vec(&compressed).unwrap() == data);
+        assert!(unsafe { decompress_to_vec_trusted(&compressed) }.unwrap() == data);
+    }
 }
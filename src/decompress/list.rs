@@ -0,0 +1,165 @@
+use crate::decompress::parallel::{scan_blocks, BlockScan};
+use crate::decompress::sequences::Sequences;
+use crate::decompress::split_header;
+use crate::errors::GlzError;
+use crate::frame::FrameHeader;
+use crate::utils::BlockPrefix;
+
+/// A block of a stream, as listed by [`list_blocks`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockInfo
+{
+    /// Offset of the block's length prefix in the stream
+    pub offset:            usize,
+    /// Size of the block's data, without its prefix and checksum
+    pub compressed_size:   usize,
+    /// Size of the block once decoded, zero for dictionary records
+    pub decompressed_size: usize,
+    /// Number of sequences in the block, zero for stored blocks and dictionary records
+    pub sequences:         usize,
+    /// The block's matches may reach into the blocks before it
+    pub linked:            bool,
+    /// The block is its own uncompressed bytes
+    pub stored:            bool,
+    /// The block is a dictionary record, naming the dictionary the blocks after it use
+    pub dictionary:        bool,
+    /// Checksum of the decoded block recorded after it
+    pub checksum:          Option<u32>
+}
+
+/// The layout of a stream, as listed by [`list_blocks`]
+#[derive(Clone, Debug)]
+pub struct StreamInfo
+{
+    pub header:           Option<FrameHeader>,
+    pub blocks:           Vec<BlockInfo>,
+    /// Checksum of the whole content recorded after the blocks
+    pub content_checksum: Option<u32>,
+    /// Offset where the blocks end, after the end mark and content checksum.
+    /// The block index of a seekable stream follows
+    pub end:              usize
+}
+
+impl StreamInfo
+{
+    /// Size of the content the blocks decode to
+    pub fn decompressed_size(&self) -> u64
+    {
+        self.blocks
+            .iter()
+            .map(|block| block.decompressed_size as u64)
+            .sum()
+    }
+}
+
+/// List the blocks of `input` with their sizes and number of sequences, without decoding them.
+///
+/// The sequences of every block are walked to find its decoded size, checking the lengths
+/// of each against the block but copying nothing. Matches reaching into data before the
+/// block are not checked, use [`verify`](crate::decompress::verify) to check a stream
+/// decodes.
+///
+/// Errors name the first malformed block and its offset
+pub fn list_blocks(input: &[u8]) -> Result<StreamInfo, GlzError>
+{
+    let (header, start) = split_header(input)?;
+    let BlockScan {
+        blocks,
+        content_checksum,
+        end
+    } = scan_blocks(input, start, header)?;
+
+    let blocks = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            let data = &input[block.start..block.start + block.prefix.size];
+            let (decompressed_size, sequences) = block_sizes(data, block.prefix)
+                .map_err(|e| e.at_block(i, block.start))?;
+
+            Ok(BlockInfo {
+                offset: block.start - 4,
+                compressed_size: block.prefix.size,
+                decompressed_size,
+                sequences,
+                linked: block.prefix.linked,
+                stored: block.prefix.stored,
+                dictionary: block.prefix.dictionary,
+                checksum: block.checksum
+            })
+        })
+        .collect::<Result<_, GlzError>>()?;
+
+    Ok(StreamInfo {
+        header,
+        blocks,
+        content_checksum: content_checksum.map(|(checksum, _)| checksum),
+        end
+    })
+}
+
+/// Decoded size and number of sequences of the block `data`
+fn block_sizes(data: &[u8], prefix: BlockPrefix) -> Result<(usize, usize), GlzError>
+{
+    if prefix.dictionary
+    {
+        // the record is the id of its dictionary
+        if data.len() < 4
+        {
+            return Err(GlzError::Truncated {
+                block:  0,
+                offset: data.len()
+            });
+        }
+        return Ok((0, 0));
+    }
+    if prefix.stored
+    {
+        return Ok((data.len(), 0));
+    }
+    let mut size = 0;
+    let mut sequences = 0;
+
+    for sequence in Sequences::new(data)
+    {
+        let sequence = sequence?;
+
+        size += sequence.ll + sequence.ml;
+        sequences += 1;
+    }
+    Ok((size, sequences))
+}
+
+#[test]
+fn list_blocks_matches_the_content()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::constants::BLOCK_SIZE;
+    use crate::utils::pseudo_random;
+
+    // compressible blocks, then random ones that are stored
+    let mut data = b"Listing walks the sequences without copying them. ".repeat(BLOCK_SIZE / 20);
+    data.truncate(2 * BLOCK_SIZE);
+    data.extend(pseudo_random(BLOCK_SIZE + 1000, 0x6c15_7b10));
+
+    for seekable in [false, true]
+    {
+        let options = CompressOptions {
+            seekable,
+            block_checksums: true,
+            content_checksum: true,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+        let info = list_blocks(&compressed).unwrap();
+
+        assert_eq!(info.decompressed_size(), data.len() as u64);
+        assert_eq!(info.blocks.len(), 4);
+        assert!(info.blocks[..2].iter().all(|b| !b.stored && b.sequences > 0));
+        assert!(info.blocks[2].stored && info.blocks[2].sequences == 0);
+        assert!(info.blocks.iter().all(|b| b.checksum.is_some()));
+        assert!(info.content_checksum.is_some());
+        // only the block index follows the blocks
+        assert_eq!(info.end == compressed.len(), !seekable);
+    }
+}
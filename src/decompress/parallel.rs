@@ -0,0 +1,348 @@
+use std::thread;
+
+use crate::checksum::Xxh32;
+use crate::constants::{MEM_SIZE, SLOP_BYTES};
+use crate::decompress::{split_header, OutputWindow};
+use crate::errors::GlzError;
+use crate::frame::{
+    check_block_checksum, check_content_checksum, read_checksum, FrameHeader, END_MARK
+};
+use crate::utils::BlockPrefix;
+
+/// A block found by [`scan_blocks`]
+#[derive(Copy, Clone, Debug)]
+pub(super) struct BlockEntry
+{
+    // offset of the block's data in the stream, after its prefix
+    pub start:    usize,
+    pub prefix:   BlockPrefix,
+    // checksum recorded after the block
+    pub checksum: Option<u32>
+}
+
+/// The blocks of a stream, found by [`scan_blocks`]
+pub(super) struct BlockScan
+{
+    // dictionary records included, so indices match those of list_blocks
+    pub blocks:           Vec<BlockEntry>,
+    // content checksum recorded after the end mark, and its offset
+    pub content_checksum: Option<(u32, usize)>,
+    // offset where the stream ends, after the end mark and content checksum
+    pub end:              usize
+}
+
+/// Walk the length prefixes of `input` from `position`, checking every block is complete.
+///
+/// `header` says whether blocks are followed by checksums and the blocks by an end mark
+pub(super) fn scan_blocks(
+    input: &[u8], mut position: usize, header: Option<FrameHeader>
+) -> Result<BlockScan, GlzError>
+{
+    let block_checksums = header.is_some_and(|header| header.block_checksums);
+    let content_checksum = header.is_some_and(|header| header.content_checksum);
+    let end_mark = header.is_some_and(|header| header.has_end_mark());
+    let mut blocks = Vec::new();
+
+    while position < input.len()
+    {
+        let block = blocks.len();
+        let prefix_bytes = input
+            .get(position..position + 4)
+            .ok_or(GlzError::Truncated {
+                block,
+                offset: position
+            })?;
+
+        if end_mark && prefix_bytes == END_MARK
+        {
+            // the content checksum and block index are not blocks
+            let content_checksum = if content_checksum
+            {
+                Some((read_checksum(input, position + 4, block)?, position + 4))
+            }
+            else
+            {
+                None
+            };
+            let end = position + if content_checksum.is_some() { 8 } else { 4 };
+
+            return Ok(BlockScan {
+                blocks,
+                content_checksum,
+                end
+            });
+        }
+        let prefix = BlockPrefix::from_bytes(prefix_bytes.try_into().unwrap());
+
+        if prefix.size > MEM_SIZE
+        {
+            return Err(GlzError::BlockTooLarge {
+                block,
+                offset: position,
+                size: prefix.size
+            });
+        }
+        position += 4;
+
+        if input.len() - position < prefix.size
+        {
+            return Err(GlzError::Truncated {
+                block,
+                offset: input.len()
+            });
+        }
+        let start = position;
+        position += prefix.size;
+
+        // dictionary records carry no checksum
+        let checksum = if block_checksums && !prefix.dictionary
+        {
+            position += 4;
+            Some(read_checksum(input, position - 4, block)?)
+        }
+        else
+        {
+            None
+        };
+        blocks.push(BlockEntry {
+            start,
+            prefix,
+            checksum
+        });
+    }
+    if end_mark
+    {
+        // the stream stopped before its end mark
+        return Err(GlzError::Truncated {
+            block:  blocks.len(),
+            offset: input.len()
+        });
+    }
+    Ok(BlockScan {
+        blocks,
+        content_checksum: None,
+        end: position
+    })
+}
+
+/// Decode the blocks of `input` on up to `threads` threads,
+/// passing every decoded block and its index to `output` in order.
+/// Returns the offset where the stream ends, anything after it such as
+/// the block index of a seekable stream is not read.
+///
+/// Indices, both those given to `output` and those of errors, count dictionary records
+/// as blocks, numbering blocks as [`list_blocks`](super::list_blocks) and `glz dump` do.
+/// A record fails with [`GlzError::DictionaryMismatch`] when its turn comes, since there
+/// is no dictionary to decode the blocks after it with.
+///
+/// The frame header and prefixes are scanned up front, then batches of one block per thread
+/// are decoded into each thread's own [`OutputWindow`] with the checked decoder.
+/// A stream with linked blocks is decoded on the calling thread, since its blocks
+/// need the ones before them. No more threads are used than there are cores or blocks,
+/// each window grows to the largest block it decodes.
+///
+/// Batches run in lockstep, each in a thread scope of its own that ends with its slowest
+/// block, so a thread done early sits idle until the rest of the batch is decoded.
+/// Blocks are the same size and decode at similar speeds, so this costs little on most
+/// streams, but a batch mixing stored blocks with dense ones waits for the dense ones.
+/// Long lived workers fed by a queue, with a reorder buffer for the output, would not wait
+pub(crate) fn decode_parallel(
+    input: &[u8], threads: usize, output: impl FnMut(usize, &[u8]) -> Result<(), GlzError>
+) -> Result<usize, GlzError>
+{
+    // SAFETY: The checked decoder has no requirements
+    unsafe { decode_parallel_with::<true>(input, threads, output) }
+}
+
+/// Like [`decode_parallel`], using the fast [`decode_sequences_at`](super::decode_sequences_at).
+///
+/// # Safety
+/// `input` must come from a trusted source, see [`decode_sequences`](super::decode_sequences).
+/// Blocks too close to its end for the slack bytes the fast path reads are copied first
+pub(crate) unsafe fn decode_parallel_trusted(
+    input: &[u8], threads: usize, output: impl FnMut(usize, &[u8]) -> Result<(), GlzError>
+) -> Result<usize, GlzError>
+{
+    decode_parallel_with::<false>(input, threads, output)
+}
+
+unsafe fn decode_parallel_with<const SAFE: bool>(
+    input: &[u8], threads: usize, mut output: impl FnMut(usize, &[u8]) -> Result<(), GlzError>
+) -> Result<usize, GlzError>
+{
+    let (header, start) = split_header(input)?;
+    let BlockScan {
+        blocks,
+        content_checksum,
+        end
+    } = scan_blocks(input, start, header)?;
+
+    if let Some(id) = header.and_then(|header| header.dictionary_id)
+    {
+        // there is no dictionary to give here
+        return Err(GlzError::DictionaryMismatch {
+            block:    0,
+            offset:   0,
+            expected: Some(id),
+            found:    None
+        });
+    }
+
+    let threads = if blocks.iter().any(|block| block.prefix.linked)
+    {
+        1
+    }
+    else
+    {
+        let cores = thread::available_parallelism().map_or(1, usize::from);
+
+        threads.clamp(1, blocks.len().clamp(1, cores))
+    };
+    // every thread's window, and its copy of blocks the fast path would read past the input
+    let mut workers: Vec<(OutputWindow, Vec<u8>)> =
+        (0..threads).map(|_| (OutputWindow::new(), Vec::new())).collect();
+    let mut decoded = 0;
+    let mut checksum = Xxh32::new(0);
+
+    for (batch_index, batch) in blocks.chunks(threads).enumerate()
+    {
+        let jobs = workers.iter_mut().zip(batch);
+
+        // SAFETY: Upheld by the caller of decode_parallel_trusted
+        let results: Vec<Result<(), GlzError>> = if batch.len() == 1
+        {
+            jobs.map(|(worker, block)| unsafe { decode_block::<SAFE>(worker, input, block) })
+                .collect()
+        }
+        else
+        {
+            thread::scope(|scope| {
+                let handles: Vec<_> = jobs
+                    .map(|(worker, block)| {
+                        scope.spawn(move || unsafe { decode_block::<SAFE>(worker, input, block) })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            })
+        };
+
+        for (i, (result, block)) in results.into_iter().zip(batch).enumerate()
+        {
+            // the block's index in the stream, dictionary records included
+            let index = batch_index * threads + i;
+
+            result.map_err(|e| e.at_block(index, block.start))?;
+            let block = workers[i].0.block();
+
+            output(index, block)?;
+            decoded += block.len() as u64;
+
+            if content_checksum.is_some()
+            {
+                checksum.update(block);
+            }
+        }
+    }
+    if let Some(header) = header
+    {
+        header.check_content_size(decoded, blocks.len(), input.len())?;
+    }
+    if let Some((expected, offset)) = content_checksum
+    {
+        check_content_checksum(checksum.finish(), expected, blocks.len(), offset)?;
+    }
+    Ok(end)
+}
+
+unsafe fn decode_block<const SAFE: bool>(
+    (window, scratch): &mut (OutputWindow, Vec<u8>), input: &[u8], block: &BlockEntry
+) -> Result<(), GlzError>
+{
+    let size = block.prefix.size;
+    let data = &input[block.start..block.start + size];
+
+    if block.prefix.dictionary
+    {
+        // there is no dictionary to give here, this reports the mismatch
+        return window.load_dictionary(data, None);
+    }
+    let decoded = if SAFE
+    {
+        window.decode(data, block.prefix)?
+    }
+    else
+    {
+        // the fast path reads up to SLOP_BYTES past the block
+        let data = if block.start + size + SLOP_BYTES <= input.len()
+        {
+            &input[block.start..]
+        }
+        else
+        {
+            scratch.clear();
+            scratch.extend_from_slice(data);
+            scratch.resize(size + SLOP_BYTES, 0);
+            &scratch[..]
+        };
+        // SAFETY: Upheld by the caller of decode_parallel_trusted
+        unsafe { window.decode_trusted(data, block.prefix)? }
+    };
+
+    match block.checksum
+    {
+        Some(expected) => check_block_checksum(decoded, expected, 0, size),
+        None => Ok(())
+    }
+}
+
+#[test]
+fn parallel_decode_matches_sequential()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::constants::BLOCK_SIZE;
+    use crate::decompress::{decompress_to_vec_parallel, decompress_to_vec_parallel_trusted};
+
+    let data = b"Blocks decode on their own threads, and come out in order. ".repeat(30_000);
+    assert!(data.len() > 5 * BLOCK_SIZE);
+
+    for linked in [false, true]
+    {
+        let options = CompressOptions {
+            linked,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+
+        for threads in [1, 3, 8]
+        {
+            assert!(decompress_to_vec_parallel(&compressed, threads).unwrap() == data);
+            // the last blocks are copied to give the fast path its slack bytes
+            let trusted = unsafe { decompress_to_vec_parallel_trusted(&compressed, threads) };
+            assert!(trusted.unwrap() == data);
+        }
+    }
+
+    // a damaged block is reported with its index, whichever thread decoded it
+    let mut compressed = compress_to_vec_with(&data, &CompressOptions::default());
+    let blocks = scan_blocks(&compressed, 0, None).unwrap().blocks;
+    // a literal run longer than the block
+    compressed[blocks[4].start..][..6].copy_from_slice(&[0x07, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+
+    let err = decompress_to_vec_parallel(&compressed, 3).unwrap_err();
+    assert_eq!(err.block(), 4);
+
+    // a dictionary record is a block, numbered as list_blocks numbers it
+    let options = CompressOptions {
+        header: false,
+        ..Default::default()
+    };
+    let with_dict = crate::compress::compress_with_dict(&data[..1000], &data[..100], &options);
+    assert!(crate::decompress::list_blocks(&with_dict).unwrap().blocks[0].dictionary);
+
+    let err = decompress_to_vec_parallel(&with_dict, 3).unwrap_err();
+    assert!(matches!(err, GlzError::DictionaryMismatch { block: 0, .. }));
+}
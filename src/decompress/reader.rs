@@ -0,0 +1,372 @@
+use std::io::{self, BufRead, ErrorKind, Read};
+
+use crate::checksum::Xxh32;
+use crate::constants::MEM_SIZE;
+use crate::decompress::OutputWindow;
+use crate::errors::GlzError;
+use crate::frame::{
+    check_block_checksum, check_content_checksum, FrameHeader, END_MARK, FIXED_HEADER_SIZE,
+    FRAME_MAGIC
+};
+use crate::utils::BlockPrefix;
+
+/// A streaming decompressor.
+///
+/// Pulls one length prefixed block at a time from the underlying reader, decodes it
+/// into an internal buffer and serves the bytes through [`Read`] and [`BufRead`].
+/// The underlying reader does not need to be seekable or have a known length,
+/// reaching its end at a block boundary is a clean end of stream.
+///
+/// Streams with and without a frame header are read. Blocks are decoded with
+/// [`decode_sequences_safe`](crate::decompress::decode_sequences_safe),
+/// keeping the history linked blocks need. Errors are returned as [`io::Error`]s
+/// wrapping a [`GlzError`].
+///
+/// # Example
+/// ```
+/// use std::io::Read;
+///
+/// let compressed = glz::compress_to_vec(b"hello hello hello hello");
+///
+/// let mut reader = glz::GlzReader::new(&compressed[..]);
+/// let mut out = Vec::new();
+/// reader.read_to_end(&mut out).unwrap();
+///
+/// assert_eq!(out, b"hello hello hello hello");
+/// ```
+pub struct GlzReader<R: Read>
+{
+    inner:         R,
+    // compressed bytes of the current block
+    max_in:        Vec<u8>,
+    // decoded bytes of the current block, window.block()[position..length] are yet to be read
+    window:        OutputWindow,
+    position:      usize,
+    length:        usize,
+    block_index:   usize,
+    // offset of the next block in the compressed stream
+    stream_offset: usize,
+    // the frame header, once read
+    header:        Option<FrameHeader>,
+    // bytes decoded so far
+    decoded:       u64,
+    // checksum of the bytes decoded so far, for streams ending with one
+    checksum:      Option<Xxh32>,
+    finished:      bool
+}
+
+impl<R: Read> GlzReader<R>
+{
+    /// Create a new decompressor reading from `inner`
+    pub fn new(inner: R) -> GlzReader<R>
+    {
+        GlzReader {
+            inner,
+            max_in: Vec::new(),
+            window: OutputWindow::new(),
+            position: 0,
+            length: 0,
+            block_index: 0,
+            stream_offset: 0,
+            header: None,
+            decoded: 0,
+            checksum: None,
+            finished: false
+        }
+    }
+
+    /// Get a reference to the underlying reader
+    pub fn get_ref(&self) -> &R
+    {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly will corrupt the compressed stream.
+    pub fn get_mut(&mut self) -> &mut R
+    {
+        &mut self.inner
+    }
+
+    /// Return the underlying reader, discarding any decoded bytes not yet read
+    pub fn into_inner(self) -> R
+    {
+        self.inner
+    }
+
+    /// Read and decode blocks up to the next one with any bytes, returning them all
+    /// as read, or `None` at the end of the stream.
+    ///
+    /// Bytes of the current block not yet read are skipped
+    pub(super) fn next_decoded(&mut self) -> Result<Option<&[u8]>, GlzError>
+    {
+        while !self.finished
+        {
+            let block_index = self.block_index;
+
+            if !self.next_block()?
+            {
+                self.finished = true;
+            }
+            else if self.block_index != block_index && self.length > 0
+            {
+                // headers and dictionary records decode to nothing
+                self.position = self.length;
+
+                return Ok(Some(self.window.block()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Number of bytes of the compressed stream read so far
+    pub(super) fn compressed_position(&self) -> usize
+    {
+        self.stream_offset
+    }
+
+    /// Read and decode the next block.
+    ///
+    /// Returns `false` if the stream ended at a block boundary.
+    fn next_block(&mut self) -> Result<bool, GlzError>
+    {
+        let mut size_bytes = [0; 4];
+
+        let (block, offset) = (self.block_index, self.stream_offset);
+        let read_error = |error: io::Error| {
+            if error.kind() == ErrorKind::UnexpectedEof
+            {
+                GlzError::Truncated { block, offset }
+            }
+            else
+            {
+                GlzError::Io {
+                    block,
+                    offset,
+                    error
+                }
+            }
+        };
+
+        if !read_prefix(&mut self.inner, &mut size_bytes).map_err(read_error)?
+        {
+            if self.header.is_some_and(|header| header.has_end_mark())
+            {
+                // the stream stopped before its end mark
+                return Err(GlzError::Truncated { block, offset });
+            }
+            if let Some(header) = self.header
+            {
+                header.check_content_size(self.decoded, block, offset)?;
+            }
+            return Ok(false);
+        }
+        if size_bytes == END_MARK && self.header.is_some_and(|header| header.has_end_mark())
+        {
+            // the end of the stream, the block index is left unread
+            self.header
+                .unwrap()
+                .check_content_size(self.decoded, block, offset)?;
+
+            self.stream_offset += 4;
+
+            if let Some(checksum) = &self.checksum
+            {
+                let mut expected = [0; 4];
+                self.inner
+                    .read_exact(&mut expected)
+                    .map_err(read_error)?;
+
+                check_content_checksum(
+                    checksum.finish(),
+                    u32::from_le_bytes(expected),
+                    block,
+                    offset + 4
+                )?;
+                self.stream_offset += 4;
+            }
+            return Ok(false);
+        }
+        if offset == 0 && size_bytes == FRAME_MAGIC
+        {
+            // the frame header, the first block follows
+            let mut bytes = FRAME_MAGIC.to_vec();
+
+            bytes.resize(FIXED_HEADER_SIZE, 0);
+            self.inner
+                .read_exact(&mut bytes[4..])
+                .map_err(read_error)?;
+            bytes.resize(FrameHeader::encoded_size(bytes[5]), 0);
+            self.inner
+                .read_exact(&mut bytes[FIXED_HEADER_SIZE..])
+                .map_err(read_error)?;
+
+            let (header, size) = FrameHeader::parse(&bytes)?.unwrap();
+
+            if let Some(id) = header.dictionary_id
+            {
+                // there is no dictionary to give a reader
+                self.window.load_dictionary(&id.to_le_bytes(), None)?;
+            }
+            self.header = Some(header);
+            self.checksum = header.content_checksum.then(|| Xxh32::new(0));
+            self.stream_offset += size;
+
+            return Ok(true);
+        }
+        let prefix = BlockPrefix::from_bytes(size_bytes);
+        let size = prefix.size;
+
+        if size > MEM_SIZE
+        {
+            return Err(GlzError::BlockTooLarge {
+                block,
+                offset,
+                size
+            });
+        }
+        if prefix.dictionary
+        {
+            self.max_in.resize(size, 0);
+            self.inner
+                .read_exact(&mut self.max_in)
+                .map_err(read_error)?;
+
+            // there is no dictionary to give a reader
+            self.window
+                .load_dictionary(&self.max_in, None)
+                .map_err(|e| e.at_block(block, offset + 4))?;
+            self.stream_offset += size + 4;
+
+            return Ok(true);
+        }
+        // the block and its checksum, if it has one
+        let checksum_size = if self.header.is_some_and(|header| header.block_checksums)
+        {
+            4
+        }
+        else
+        {
+            0
+        };
+        self.max_in.resize(size + checksum_size, 0);
+        self.inner
+            .read_exact(&mut self.max_in)
+            .map_err(read_error)?;
+
+        let decoded = self
+            .window
+            .decode(&self.max_in[..size], prefix)
+            .map_err(|e| e.at_block(block, offset + 4))?;
+
+        if checksum_size > 0
+        {
+            let expected = u32::from_le_bytes(self.max_in[size..].try_into().unwrap());
+            check_block_checksum(decoded, expected, block, offset + 4 + size)?;
+        }
+        if let Some(checksum) = &mut self.checksum
+        {
+            checksum.update(decoded);
+        }
+        self.length = decoded.len();
+        self.decoded += self.length as u64;
+        self.position = 0;
+        self.block_index += 1;
+        self.stream_offset += size + 4 + checksum_size;
+
+        Ok(true)
+    }
+}
+
+/// Read a block length prefix, returning `false` if the reader is at its end
+fn read_prefix<R: Read>(reader: &mut R, buf: &mut [u8; 4]) -> Result<bool, io::Error>
+{
+    let mut filled = 0;
+
+    while filled < buf.len()
+    {
+        match reader.read(&mut buf[filled..])
+        {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted =>
+            {}
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(true)
+}
+
+pub(super) fn to_io_error(error: GlzError) -> io::Error
+{
+    let kind = match error
+    {
+        GlzError::Io { ref error, .. } => error.kind(),
+        GlzError::Truncated { .. } => ErrorKind::UnexpectedEof,
+        _ => ErrorKind::InvalidData
+    };
+    io::Error::new(kind, error)
+}
+
+impl<R: Read> Read for GlzReader<R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+
+        Ok(length)
+    }
+}
+
+impl<R: Read> BufRead for GlzReader<R>
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]>
+    {
+        // loop, since a block may decode to nothing
+        while self.position == self.length && !self.finished
+        {
+            if !self.next_block().map_err(to_io_error)?
+            {
+                self.finished = true;
+            }
+        }
+        Ok(&self.window.block()[self.position..self.length])
+    }
+
+    fn consume(&mut self, amt: usize)
+    {
+        self.position = (self.position + amt).min(self.length);
+    }
+}
+
+#[test]
+fn reader_small_reads_and_truncation()
+{
+    let data = b"The quick brown fox jumps over the lazy dog. ".repeat(15000);
+    let compressed = crate::compress::compress_to_vec(&data);
+
+    let mut reader = GlzReader::new(&compressed[..]);
+    let mut out = Vec::new();
+    let mut buf = [0; 1013];
+
+    loop
+    {
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0
+        {
+            break;
+        }
+        out.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(out, data);
+
+    let mut reader = GlzReader::new(&compressed[..compressed.len() - 2]);
+    let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
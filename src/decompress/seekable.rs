@@ -0,0 +1,432 @@
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom};
+
+use crate::constants::MEM_SIZE;
+use crate::decompress::reader::to_io_error;
+use crate::decompress::OutputWindow;
+use crate::errors::GlzError;
+use crate::frame::{
+    check_block_checksum, parse_index, parse_index_footer, FrameHeader, IndexEntry,
+    FIXED_HEADER_SIZE, FRAME_MAGIC, INDEX_ENTRY_SIZE, INDEX_FOOTER_SIZE
+};
+use crate::utils::BlockPrefix;
+
+/// A decompressor for seekable streams, with random access to the decompressed bytes.
+///
+/// Reads the frame header and the block index at the end of a stream written with
+/// [`CompressOptions::seekable`](crate::compress::CompressOptions::seekable), then decodes
+/// only the block holding the current position, seeking the underlying reader to it.
+/// The last decoded block is kept, so reading through a block decodes it once.
+///
+/// Block checksums are verified when a block is decoded. The content checksum is not,
+/// since the content is rarely read as a whole. Errors are returned as [`io::Error`]s
+/// wrapping a [`GlzError`].
+///
+/// # Example
+/// ```
+/// use std::io::{Cursor, Read, Seek, SeekFrom};
+///
+/// let data = b"record one, record two, record three".repeat(10_000);
+/// let options = glz::CompressOptions {
+///     seekable: true,
+///     ..Default::default()
+/// };
+/// let compressed = glz::compress_to_vec_with(&data, &options);
+///
+/// let mut reader = glz::SeekableReader::new(Cursor::new(compressed)).unwrap();
+/// let mut record = [0; 10];
+///
+/// reader.seek(SeekFrom::Start(300_012)).unwrap();
+/// reader.read_exact(&mut record).unwrap();
+/// assert_eq!(&record, &data[300_012..300_022]);
+/// ```
+pub struct SeekableReader<R: Read + Seek>
+{
+    inner:    R,
+    header:   FrameHeader,
+    index:    Vec<IndexEntry>,
+    // decompressed offset of every block, followed by the decompressed size of the stream
+    starts:   Vec<u64>,
+    // compressed bytes of the current block
+    max_in:   Vec<u8>,
+    window:   OutputWindow,
+    // block in `window`, if any
+    current:  Option<usize>,
+    // position in the decompressed stream
+    position: u64
+}
+
+impl<R: Read + Seek> SeekableReader<R>
+{
+    /// Open the seekable stream in `inner`, reading its frame header and block index.
+    ///
+    /// Fails with [`GlzError::InvalidIndex`] if the stream has no block index
+    /// or the index does not fit in it
+    pub fn new(mut inner: R) -> Result<SeekableReader<R>, GlzError>
+    {
+        let io_error = |offset: u64| {
+            move |error: io::Error| {
+                if error.kind() == ErrorKind::UnexpectedEof
+                {
+                    GlzError::Truncated {
+                        block:  0,
+                        offset: offset as usize
+                    }
+                }
+                else
+                {
+                    GlzError::Io {
+                        block: 0,
+                        offset: offset as usize,
+                        error
+                    }
+                }
+            }
+        };
+        let invalid = |offset: u64, reason| GlzError::InvalidIndex {
+            block: 0,
+            offset: offset as usize,
+            reason
+        };
+
+        // the frame header says whether the stream has an index
+        let mut bytes = vec![0; FIXED_HEADER_SIZE];
+
+        inner.rewind().map_err(io_error(0))?;
+        inner.read_exact(&mut bytes).map_err(io_error(0))?;
+
+        if !bytes.starts_with(&FRAME_MAGIC)
+        {
+            return Err(invalid(0, "stream has no frame header"));
+        }
+        bytes.resize(FrameHeader::encoded_size(bytes[5]), 0);
+        inner
+            .read_exact(&mut bytes[FIXED_HEADER_SIZE..])
+            .map_err(io_error(0))?;
+
+        let (header, header_size) = FrameHeader::parse(&bytes)?.unwrap();
+
+        if !header.seekable
+        {
+            return Err(invalid(0, "stream has no block index"));
+        }
+        if let Some(id) = header.dictionary_id
+        {
+            // there is no dictionary to give a reader
+            return Err(GlzError::DictionaryMismatch {
+                block:    0,
+                offset:   0,
+                expected: Some(id),
+                found:    None
+            });
+        }
+
+        // the index ends the stream
+        let length = inner.seek(SeekFrom::End(0)).map_err(io_error(0))?;
+        let mut footer = [0; INDEX_FOOTER_SIZE];
+
+        let footer_start = length
+            .checked_sub(INDEX_FOOTER_SIZE as u64)
+            .filter(|&start| start >= header_size as u64)
+            .ok_or(invalid(length, "stream too short for an index"))?;
+
+        inner
+            .seek(SeekFrom::Start(footer_start))
+            .map_err(io_error(footer_start))?;
+        inner
+            .read_exact(&mut footer)
+            .map_err(io_error(footer_start))?;
+
+        let count = parse_index_footer(&footer).map_err(|e| e.at_block(0, footer_start as usize))?;
+        let index_start = (count as u64)
+            .checked_mul(INDEX_ENTRY_SIZE as u64)
+            .and_then(|size| footer_start.checked_sub(size))
+            .filter(|&start| start >= header_size as u64)
+            .ok_or(invalid(footer_start, "index larger than the stream"))?;
+
+        let mut entries = vec![0; count * INDEX_ENTRY_SIZE];
+
+        inner
+            .seek(SeekFrom::Start(index_start))
+            .map_err(io_error(index_start))?;
+        inner
+            .read_exact(&mut entries)
+            .map_err(io_error(index_start))?;
+
+        let index = parse_index(&entries);
+        let mut starts = Vec::with_capacity(index.len() + 1);
+        let mut next_offset = header_size as u64;
+        let mut total = 0;
+
+        for entry in &index
+        {
+            // blocks come in order, each at least a length prefix long
+            if entry.compressed_offset < next_offset || entry.compressed_offset >= index_start
+            {
+                return Err(invalid(index_start, "block offset out of order"));
+            }
+            if entry.decompressed_size as usize > header.block_size
+            {
+                return Err(invalid(index_start, "block larger than the block size"));
+            }
+            starts.push(total);
+            next_offset = entry.compressed_offset + 4;
+            total += u64::from(entry.decompressed_size);
+        }
+        starts.push(total);
+        header.check_content_size(total, index.len(), index_start as usize)?;
+
+        Ok(SeekableReader {
+            inner,
+            header,
+            index,
+            starts,
+            max_in: Vec::new(),
+            window: OutputWindow::new(),
+            current: None,
+            position: 0
+        })
+    }
+
+    /// Size of the decompressed stream
+    pub fn len(&self) -> u64
+    {
+        *self.starts.last().unwrap()
+    }
+
+    /// Whether the decompressed stream is empty
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// The frame header of the stream
+    pub fn header(&self) -> &FrameHeader
+    {
+        &self.header
+    }
+
+    /// The blocks of the stream, as recorded in its index
+    pub fn blocks(&self) -> &[IndexEntry]
+    {
+        &self.index
+    }
+
+    /// Get a reference to the underlying reader
+    pub fn get_ref(&self) -> &R
+    {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying reader.
+    ///
+    /// Seeking it is fine, every block is read from the offset in the index
+    pub fn get_mut(&mut self) -> &mut R
+    {
+        &mut self.inner
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R
+    {
+        self.inner
+    }
+
+    /// Read and decode block `block` into the window
+    fn load_block(&mut self, block: usize) -> Result<(), GlzError>
+    {
+        let entry = self.index[block];
+        let offset = entry.compressed_offset as usize;
+
+        let read_error = |error: io::Error| {
+            if error.kind() == ErrorKind::UnexpectedEof
+            {
+                GlzError::Truncated { block, offset }
+            }
+            else
+            {
+                GlzError::Io {
+                    block,
+                    offset,
+                    error
+                }
+            }
+        };
+        let invalid = |reason| GlzError::InvalidIndex {
+            block,
+            offset,
+            reason
+        };
+
+        let mut prefix_bytes = [0; 4];
+
+        self.current = None;
+        self.inner
+            .seek(SeekFrom::Start(entry.compressed_offset))
+            .map_err(read_error)?;
+        self.inner
+            .read_exact(&mut prefix_bytes)
+            .map_err(read_error)?;
+
+        let prefix = BlockPrefix::from_bytes(prefix_bytes);
+        let size = prefix.size;
+
+        if size > MEM_SIZE
+        {
+            return Err(GlzError::BlockTooLarge {
+                block,
+                offset,
+                size
+            });
+        }
+        if prefix.linked || prefix.dictionary
+        {
+            return Err(invalid("index does not point at an independent block"));
+        }
+        let checksum_size = if self.header.block_checksums { 4 } else { 0 };
+
+        self.max_in.resize(size + checksum_size, 0);
+        self.inner
+            .read_exact(&mut self.max_in)
+            .map_err(read_error)?;
+
+        let decoded = self
+            .window
+            .decode(&self.max_in[..size], prefix)
+            .map_err(|e| e.at_block(block, offset + 4))?;
+
+        if checksum_size > 0
+        {
+            let expected = u32::from_le_bytes(self.max_in[size..].try_into().unwrap());
+            check_block_checksum(decoded, expected, block, offset + 4 + size)?;
+        }
+        if decoded.len() != entry.decompressed_size as usize
+        {
+            return Err(invalid("block size does not match the index"));
+        }
+        self.current = Some(block);
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableReader<R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+
+        Ok(length)
+    }
+}
+
+impl<R: Read + Seek> BufRead for SeekableReader<R>
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]>
+    {
+        if self.position >= self.len()
+        {
+            return Ok(&[]);
+        }
+        // the last block starting at or before the position, skipping empty ones
+        let block = self.starts.partition_point(|&start| start <= self.position) - 1;
+
+        if self.current != Some(block)
+        {
+            self.load_block(block).map_err(to_io_error)?;
+        }
+        let start = (self.position - self.starts[block]) as usize;
+
+        Ok(&self.window.block()[start..])
+    }
+
+    fn consume(&mut self, amt: usize)
+    {
+        self.position = (self.position + amt as u64).min(self.len());
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableReader<R>
+{
+    /// Move to a position in the decompressed stream, without decoding anything.
+    ///
+    /// Positions past the end are allowed, reading there returns no bytes
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64>
+    {
+        let position = match pos
+        {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta)
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn seekable_reader_reads_any_range()
+{
+    use std::io::{Cursor, Write};
+
+    use crate::compress::{compress_to_vec_with, CompressOptions, GlzWriter};
+    use crate::constants::BLOCK_SIZE;
+    use crate::decompress::{decompress_to_vec, decompress_to_vec_parallel, GlzReader};
+
+    let data: Vec<u8> = (0..3 * BLOCK_SIZE + 5000)
+        .map(|i| (i / 3 + i / 1000) as u8)
+        .collect();
+    let options = CompressOptions {
+        seekable: true,
+        block_checksums: true,
+        content_checksum: true,
+        linked: true,
+        ..Default::default()
+    };
+
+    // flushing writes short blocks, which the index records
+    let mut writer = GlzWriter::with_options(Vec::new(), &options);
+    writer.write_all(&data[..1000]).unwrap();
+    writer.flush().unwrap();
+    writer.write_all(&data[1000..]).unwrap();
+
+    for compressed in [compress_to_vec_with(&data, &options), writer.finish().unwrap()]
+    {
+        // the other decoders stop at the end mark, before the index
+        assert!(decompress_to_vec(&compressed).unwrap() == data);
+        assert!(decompress_to_vec_parallel(&compressed, 2).unwrap() == data);
+        let mut out = Vec::new();
+        GlzReader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+        assert!(out == data);
+
+        let mut reader = SeekableReader::new(Cursor::new(&compressed)).unwrap();
+        assert_eq!(reader.len(), data.len() as u64);
+
+        // ranges inside a block, across blocks and up to the end
+        for (start, length) in [(10, 100), (BLOCK_SIZE - 50, 100), (999, 2), (data.len() - 7, 7)]
+        {
+            let mut buf = vec![0; length];
+
+            reader.seek(SeekFrom::Start(start as u64)).unwrap();
+            reader.read_exact(&mut buf).unwrap();
+            assert!(buf == data[start..start + length], "{start}");
+        }
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        assert_eq!(reader.read(&mut [0; 10]).unwrap(), 3);
+        assert_eq!(reader.read(&mut [0; 10]).unwrap(), 0);
+        assert!(reader.seek(SeekFrom::Current(-(data.len() as i64) - 1)).is_err());
+    }
+
+    // streams without an index are rejected
+    let plain = compress_to_vec_with(&data, &CompressOptions::default());
+    assert!(matches!(
+        SeekableReader::new(Cursor::new(&plain)),
+        Err(GlzError::InvalidIndex { .. })
+    ));
+}
@@ -0,0 +1,186 @@
+use crate::constants::{GLZ_MIN_MATCH, LITERAL_BITS, ML_BITS, OFFSET_BIT};
+use crate::decompress::decode_encode_mod_checked;
+use crate::errors::GlzError;
+
+/// A sequence read from a compressed block, literals followed by a match.
+///
+/// The decoder's side of an [`EncodeSequence`](crate::compress::EncodeSequence),
+/// with the same lengths and offset and the number of bytes it was encoded in
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Sequence
+{
+    /// Offset of the sequence's token in the block
+    pub start:    usize,
+    /// Offset of the sequence's literals in the block
+    pub literals: usize,
+    pub ll:       usize,
+    /// Match length, zero for the last sequence of a block, which has no match
+    pub ml:       usize,
+    /// Match offset, how far back the match starts from the end of the literals
+    pub ol:       usize,
+    /// Encoded size of the sequence in bytes
+    pub cost:     usize
+}
+
+impl Sequence
+{
+    /// The sequence's literals in `block`, the compressed block it was read from
+    pub fn literals<'a>(&self, block: &'a [u8]) -> &'a [u8]
+    {
+        &block[self.literals..self.literals + self.ll]
+    }
+}
+
+/// Read the sequence starting at `input[start..]`, checking every length
+/// and `encode_mod` value against `input`.
+///
+/// The match offset is not checked, since it depends on what was decoded before.
+/// Errors are reported relative to the start of the block, with a block index of zero.
+#[inline(always)]
+pub(crate) fn read_sequence(input: &[u8], start: usize) -> Result<Sequence, GlzError>
+{
+    let truncated = || GlzError::Truncated {
+        block:  0,
+        offset: input.len()
+    };
+    let mut input_offset = start;
+    let token = *input.get(input_offset).ok_or_else(truncated)?;
+
+    // extract bytes from token
+    let mut offset = usize::from(token >> OFFSET_BIT) & 0b011;
+    let mut match_length = (usize::from(token >> ML_BITS) & 0b111) + GLZ_MIN_MATCH;
+    let mut literal_length = usize::from(token >> LITERAL_BITS) & 0b111;
+
+    input_offset += 1;
+
+    if literal_length == 7
+    {
+        let (ll, b) = decode_encode_mod_checked(&input[input_offset..]).ok_or_else(truncated)?;
+        input_offset += b;
+        literal_length += ll;
+    }
+    let literals = input_offset;
+
+    if literal_length > input.len() - input_offset
+    {
+        return Err(GlzError::LiteralOverflow {
+            block:  0,
+            offset: start,
+            length: literal_length
+        });
+    }
+    input_offset += literal_length;
+
+    if input_offset == input.len()
+    {
+        // the last sequence ends with its literals
+        return Ok(Sequence {
+            start,
+            literals,
+            ll: literal_length,
+            ml: 0,
+            ol: 0,
+            cost: input_offset - start
+        });
+    }
+    // extract match offset
+    let (ol, b) = decode_encode_mod_checked(&input[input_offset..]).ok_or_else(truncated)?;
+    input_offset += b;
+    offset |= ol << 2;
+
+    // extract the match length
+    if match_length == (7 + GLZ_MIN_MATCH)
+    {
+        let (ml, b) = decode_encode_mod_checked(&input[input_offset..]).ok_or_else(truncated)?;
+        input_offset += b;
+        match_length += ml;
+    }
+    Ok(Sequence {
+        start,
+        literals,
+        ll: literal_length,
+        ml: match_length,
+        ol: offset,
+        cost: input_offset - start
+    })
+}
+
+/// Iterator over the sequences of a compressed block, reading each without decoding it.
+///
+/// Every length and `encode_mod` value is checked against the block, so this is safe to use
+/// on untrusted input, but match offsets are not checked against the data before them.
+/// Errors are reported relative to the start of the block, with a block index of zero,
+/// and end the iteration.
+///
+/// # Example
+/// ```
+/// use glz::decompress::Sequences;
+///
+/// let compressed = glz::compress_to_vec(&b"abcabcabcabcabcabc".repeat(10));
+/// // the block after its length prefix
+/// let block = &compressed[4..];
+///
+/// for sequence in Sequences::new(block)
+/// {
+///     let sequence = sequence.unwrap();
+///     let literals = sequence.literals(block);
+///
+///     println!("{literals:?} then {} bytes from {} back", sequence.ml, sequence.ol);
+/// }
+/// ```
+pub struct Sequences<'a>
+{
+    input:    &'a [u8],
+    position: usize
+}
+
+impl<'a> Sequences<'a>
+{
+    /// The sequences of the compressed block `input`
+    pub fn new(input: &'a [u8]) -> Sequences<'a>
+    {
+        Sequences { input, position: 0 }
+    }
+}
+
+impl Iterator for Sequences<'_>
+{
+    type Item = Result<Sequence, GlzError>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if self.position >= self.input.len()
+        {
+            return None;
+        }
+        let sequence = read_sequence(self.input, self.position);
+
+        self.position = match &sequence
+        {
+            Ok(sequence) => self.position + sequence.cost,
+            Err(_) => self.input.len()
+        };
+        Some(sequence)
+    }
+}
+
+#[test]
+fn sequences_add_up_to_the_block()
+{
+    use crate::compress::compress_to_vec;
+
+    let data = b"a sequence is literals and a match, a sequence again ".repeat(200);
+    // a single block after its length prefix
+    let compressed = &compress_to_vec(&data)[4..];
+
+    let sequences: Vec<Sequence> = Sequences::new(compressed).map(Result::unwrap).collect();
+
+    assert_eq!(sequences.iter().map(|s| s.ll + s.ml).sum::<usize>(), data.len());
+    assert_eq!(sequences.iter().map(|s| s.cost).sum::<usize>(), compressed.len());
+    assert_eq!(sequences.last().unwrap().ml, 0);
+    assert_eq!(sequences[0].literals(compressed), &data[..sequences[0].ll]);
+
+    // a literal run past the end of the block
+    let err = Sequences::new(&[0x07, 0xff, 0x7f]).last().unwrap().unwrap_err();
+    assert!(matches!(err, GlzError::LiteralOverflow { offset: 0, .. }));
+}
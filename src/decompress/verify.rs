@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::decompress::parallel::{decode_parallel, scan_blocks};
+use crate::decompress::split_header;
+use crate::errors::GlzError;
+use crate::frame::{
+    parse_index, parse_index_footer, FrameHeader, INDEX_ENTRY_SIZE, INDEX_FOOTER_SIZE
+};
+
+/// Check that `input` is a whole, undamaged stream without keeping its content,
+/// decoding up to `threads` blocks at once. Returns the number of blocks and
+/// the number of bytes they decode to.
+///
+/// Every block is decoded into a scratch buffer with the checked decoder, so this is
+/// safe to call on untrusted input, and checked against its checksum if it has one.
+/// The blocks must add up to the input: a stream that ends in the middle of a block,
+/// or goes on past its end with anything but the block index of a seekable stream, fails.
+/// So does a block index that does not match the blocks.
+///
+/// Errors name the first bad block and its offset. Streams compressed with a dictionary
+/// fail with [`GlzError::DictionaryMismatch`], since there is none to decode them with.
+pub fn verify(input: &[u8], threads: usize) -> Result<(usize, u64), GlzError>
+{
+    let (header, start) = split_header(input)?;
+    // decoded size of every block
+    let mut sizes = Vec::new();
+
+    let end = decode_parallel(input, threads, |_, block| {
+        sizes.push(block.len());
+        Ok(())
+    })?;
+    let decompressed = sizes.iter().map(|&size| size as u64).sum();
+
+    match header
+    {
+        Some(header) if header.seekable => check_index(input, header, start, end, &sizes)?,
+        _ if end != input.len() =>
+        {
+            return Err(GlzError::TrailingData {
+                block:  sizes.len(),
+                offset: end
+            });
+        }
+        _ => ()
+    }
+    Ok((sizes.len(), decompressed))
+}
+
+/// Check `input_file` with [`verify`], decoding up to `threads` blocks at once.
+///
+/// The input is memory mapped, like in [`decompress_file`](crate::decompress::decompress_file)
+pub fn verify_file(input_file: impl AsRef<Path>, threads: usize) -> Result<(usize, u64), GlzError>
+{
+    let io_error = |error| GlzError::Io {
+        block: 0,
+        offset: 0,
+        error
+    };
+    let fd = File::open(input_file).map_err(io_error)?;
+    // SAFETY: The file must not be changed while mapped, the checked decoder
+    // keeps a change from going out of bounds
+    let input = unsafe { memmap2::Mmap::map(&fd) }.map_err(io_error)?;
+
+    verify(&input, threads)
+}
+
+/// Check the block index filling `input[end..]` against the blocks from `start`,
+/// which decoded to `sizes` bytes
+fn check_index(
+    input: &[u8], header: FrameHeader, start: usize, end: usize, sizes: &[usize]
+) -> Result<(), GlzError>
+{
+    let blocks = scan_blocks(input, start, Some(header))?.blocks;
+    let invalid = |block, offset, reason| GlzError::InvalidIndex {
+        block,
+        offset,
+        reason
+    };
+
+    let trailer = &input[end..];
+    let footer = trailer
+        .len()
+        .checked_sub(INDEX_FOOTER_SIZE)
+        .ok_or(invalid(blocks.len(), end, "stream too short for an index"))?;
+    let count = parse_index_footer(trailer[footer..].try_into().unwrap())
+        .map_err(|e| e.at_block(blocks.len(), end + footer))?;
+
+    if count.checked_mul(INDEX_ENTRY_SIZE) != Some(footer)
+    {
+        return Err(invalid(blocks.len(), end, "index size does not match its entry count"));
+    }
+    let index = parse_index(&trailer[..footer]);
+
+    if index.len() != blocks.len()
+    {
+        return Err(invalid(blocks.len(), end, "index does not list every block"));
+    }
+    for (i, ((entry, block), &size)) in index.iter().zip(&blocks).zip(sizes).enumerate()
+    {
+        let offset = end + i * INDEX_ENTRY_SIZE;
+
+        // entries point at the length prefix
+        if entry.compressed_offset != (block.start - 4) as u64
+        {
+            return Err(invalid(i, offset, "block offset does not match the block"));
+        }
+        if entry.decompressed_size as usize != size
+        {
+            return Err(invalid(i, offset, "decompressed size does not match the block"));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn verify_finds_the_first_bad_block()
+{
+    use crate::compress::{compress_to_vec_with, CompressOptions};
+    use crate::constants::BLOCK_SIZE;
+
+    let data: Vec<u8> = (0..4 * BLOCK_SIZE).map(|i| (i / 7 + i / 5000) as u8).collect();
+
+    for seekable in [false, true]
+    {
+        let options = CompressOptions {
+            seekable,
+            block_checksums: true,
+            ..Default::default()
+        };
+        let compressed = compress_to_vec_with(&data, &options);
+        assert_eq!(verify(&compressed, 2).unwrap(), (4, data.len() as u64));
+
+        let (header, start) = split_header(&compressed).unwrap();
+        let blocks = scan_blocks(&compressed, start, header).unwrap().blocks;
+
+        // a flipped byte in block 2
+        let mut corrupt = compressed.clone();
+        corrupt[blocks[2].start + 100] ^= 0x40;
+        assert_eq!(verify(&corrupt, 2).unwrap_err().block(), 2);
+
+        // a stream cut in the middle of block 3
+        let err = verify(&compressed[..blocks[3].start + 10], 1).unwrap_err();
+        assert!(matches!(err, GlzError::Truncated { block: 3, .. }));
+
+        // bytes after the stream, or a damaged index
+        let mut longer = compressed.clone();
+        longer.push(0);
+        assert!(verify(&longer, 1).is_err());
+
+        if seekable
+        {
+            let mut bad_index = compressed.clone();
+            let entry = bad_index.len() - INDEX_FOOTER_SIZE - INDEX_ENTRY_SIZE;
+            bad_index[entry + 8] ^= 1;
+
+            let err = verify(&bad_index, 1).unwrap_err();
+            assert!(matches!(err, GlzError::InvalidIndex { block: 3, .. }));
+        }
+    }
+}
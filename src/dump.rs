@@ -0,0 +1,232 @@
+//! `glz dump`, the sequences of every block of a stream as they were encoded,
+//! for looking into what the compressor wrote.
+
+use std::io::{self, BufWriter, Write};
+
+use glz::decompress::{list_blocks, BlockInfo, Sequences};
+
+use crate::bench::json_string;
+
+/// What to dump
+pub struct DumpOptions
+{
+    /// Only dump this block
+    pub block:   Option<usize>,
+    /// Literal bytes shown for every sequence
+    pub preview: usize,
+    pub json:    bool
+}
+
+/// Print the sequences of the stream `input` to stdout, as a table for every block
+/// or as one JSON object per sequence
+pub fn run(input: &[u8], options: &DumpOptions) -> Result<(), String>
+{
+    let info = list_blocks(input).map_err(|e| e.to_string())?;
+
+    if let Some(block) = options.block.filter(|&block| block >= info.blocks.len())
+    {
+        return Err(format!("no block {block}, the stream has {}", info.blocks.len()));
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let result = info
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| options.block.is_none_or(|only| only == *i))
+        .try_for_each(|(i, block)| dump_block(&mut out, input, i, block, options))
+        .and_then(|_| out.flush());
+
+    match result
+    {
+        // stop quietly when piped into something like head
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.to_string()),
+        _ => Ok(())
+    }
+}
+
+fn dump_block(
+    out: &mut impl Write, input: &[u8], index: usize, block: &BlockInfo, options: &DumpOptions
+) -> io::Result<()>
+{
+    // the block's data, after its length prefix
+    let start = block.offset + 4;
+    let data = &input[start..start + block.compressed_size];
+
+    // neither holds sequences, a dictionary record is the id of its dictionary
+    if block.dictionary
+    {
+        let id = u32::from_le_bytes(data[..4].try_into().unwrap());
+
+        if options.json
+        {
+            return writeln!(
+                out,
+                "{{\"block\":{index},\"offset\":{},\"kind\":\"dictionary\",\"id\":{id}}}",
+                block.offset
+            );
+        }
+        return writeln!(out, "block {index} at {}: dictionary record, id {id:#010x}", block.offset);
+    }
+    if block.stored
+    {
+        if options.json
+        {
+            return writeln!(
+                out,
+                "{{\"block\":{index},\"offset\":{},\"kind\":\"stored\",\"size\":{}}}",
+                block.offset, block.compressed_size
+            );
+        }
+        return writeln!(
+            out,
+            "block {index} at {}: stored, {} bytes",
+            block.offset, block.compressed_size
+        );
+    }
+    if !options.json
+    {
+        writeln!(
+            out,
+            "block {index} at {}: {} bytes, {} sequences, decodes to {}{}",
+            block.offset,
+            block.compressed_size,
+            block.sequences,
+            block.decompressed_size,
+            if block.linked { ", linked" } else { "" }
+        )?;
+        writeln!(
+            out,
+            "  {:>10} {:>8} {:>6}  {:<width$} {:>8} {:>6} {:>5}",
+            "offset",
+            "position",
+            "ll",
+            "literals",
+            "ol",
+            "ml",
+            "cost",
+            width = options.preview + 5
+        )?;
+    }
+    // offset of every sequence in the block's decoded bytes
+    let mut position = 0;
+
+    // list_blocks walked the sequences of compressed blocks already, they are well formed
+    for sequence in Sequences::new(data).map_while(Result::ok)
+    {
+        let literals = preview(sequence.literals(data), options.preview);
+
+        if options.json
+        {
+            writeln!(
+                out,
+                "{{\"block\":{index},\"offset\":{},\"kind\":\"sequence\",\
+                 \"position\":{position},\"ll\":{},\"literals\":{},\"ol\":{},\"ml\":{},\
+                 \"cost\":{}}}",
+                start + sequence.start,
+                sequence.ll,
+                json_string(&literals),
+                sequence.ol,
+                sequence.ml,
+                sequence.cost
+            )?;
+        }
+        else
+        {
+            // the last sequence has no match
+            let (ol, ml) = match sequence.ml
+            {
+                0 => ("-".to_string(), "-".to_string()),
+                ml => (sequence.ol.to_string(), ml.to_string())
+            };
+            writeln!(
+                out,
+                "  {:>10} {:>8} {:>6}  {:<width$} {:>8} {:>6} {:>5}",
+                start + sequence.start,
+                position,
+                sequence.ll,
+                format!("\"{literals}\""),
+                ol,
+                ml,
+                sequence.cost,
+                width = options.preview + 5
+            )?;
+        }
+        position += sequence.ll + sequence.ml;
+    }
+    Ok(())
+}
+
+/// Up to `length` bytes of `literals`, with anything but printable ASCII escaped,
+/// ending in `...` if they were cut short
+fn preview(literals: &[u8], length: usize) -> String
+{
+    let mut preview = literals[..literals.len().min(length)].escape_ascii().to_string();
+
+    if literals.len() > length
+    {
+        preview.push_str("...");
+    }
+    preview
+}
+
+#[test]
+fn dump_skips_stored_blocks_and_dictionary_records()
+{
+    use glz::compress::{compress_with_dict, CompressOptions};
+    use glz::constants::BLOCK_SIZE;
+
+    // a dictionary record, a compressed block, then a stored one of words that never repeat
+    let mut data = b"Dumping prints every sequence. ".repeat(BLOCK_SIZE / 30);
+    data.truncate(BLOCK_SIZE);
+    let words = (0..BLOCK_SIZE as u32 / 4).map(|i| i.wrapping_mul(0x9e37_79b1));
+    data.extend(words.flat_map(u32::to_le_bytes));
+
+    let options = CompressOptions {
+        header: false,
+        ..Default::default()
+    };
+    let compressed = compress_with_dict(&data, b"Dumping prints", &options);
+    let info = list_blocks(&compressed).unwrap();
+
+    assert_eq!(info.blocks.len(), 3);
+    assert!(info.blocks[0].dictionary && info.blocks[2].stored);
+
+    for json in [false, true]
+    {
+        let options = DumpOptions {
+            block: None,
+            preview: 8,
+            json
+        };
+        let mut out = Vec::new();
+
+        for (i, block) in info.blocks.iter().enumerate()
+        {
+            dump_block(&mut out, &compressed, i, block, &options).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        // the table of the compressed block has a title and a header line
+        let table_lines = if json { 0 } else { 2 };
+
+        assert_eq!(lines.len(), 2 + table_lines + info.blocks[1].sequences);
+
+        if json
+        {
+            let stored = format!(r#""kind":"stored","size":{BLOCK_SIZE}}}"#);
+
+            assert!(lines[0].contains(r#""kind":"dictionary","id":"#));
+            assert!(lines[lines.len() - 1].ends_with(&stored));
+            assert!(lines[1..lines.len() - 1]
+                .iter()
+                .all(|line| line.contains(r#""kind":"sequence""#)));
+        }
+        else
+        {
+            assert!(lines[0].starts_with("block 0 at 0: dictionary record, id 0x"));
+            assert!(lines[1].contains(&format!("sequences, decodes to {BLOCK_SIZE}")));
+            assert!(lines[lines.len() - 1].ends_with(&format!("stored, {BLOCK_SIZE} bytes")));
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors that can occur when compressing or decompressing a GLZ stream.
+///
+/// Every variant records the index of the block being processed and the
+/// byte offset in the stream where the error was detected.
+/// For I/O errors on the compressed side the offset points into the compressed stream,
+/// for those on the uncompressed side it is the number of uncompressed bytes processed.
+#[derive(Debug)]
+pub enum GlzError
+{
+    /// Reading or writing failed
+    Io
+    {
+        block:  usize,
+        offset: usize,
+        error:  std::io::Error
+    },
+    /// The input ended in the middle of a block or a sequence
+    Truncated
+    {
+        block: usize, offset: usize
+    },
+    /// A match points further back than the data decoded so far
+    InvalidOffset
+    {
+        block:    usize,
+        offset:   usize,
+        distance: usize,
+        position: usize
+    },
+    /// A literal run extends past the end of the block
+    LiteralOverflow
+    {
+        block:  usize,
+        offset: usize,
+        length: usize
+    },
+    /// A block is larger than `MEM_SIZE`
+    BlockTooLarge
+    {
+        block:  usize,
+        offset: usize,
+        size:   usize
+    },
+    /// The decoded data does not fit in the output buffer
+    OutputOverflow
+    {
+        block: usize, offset: usize
+    },
+    /// The stream starts with a frame header this crate can not read
+    InvalidHeader
+    {
+        block:  usize,
+        offset: usize,
+        reason: &'static str
+    },
+    /// The stream decoded to a different size than its frame header records
+    ContentSizeMismatch
+    {
+        block:    usize,
+        offset:   usize,
+        expected: u64,
+        found:    u64
+    },
+    /// The dictionary given is not the one the stream was compressed with,
+    /// `expected` and `found` are the IDs of the stream's and the given dictionary
+    DictionaryMismatch
+    {
+        block:    usize,
+        offset:   usize,
+        expected: Option<u32>,
+        found:    Option<u32>
+    },
+    /// A block decoded to different bytes than its checksum records
+    BlockChecksumMismatch
+    {
+        block:    usize,
+        offset:   usize,
+        expected: u32,
+        found:    u32
+    },
+    /// The stream decoded to different bytes than its content checksum records
+    ContentChecksumMismatch
+    {
+        block:    usize,
+        offset:   usize,
+        expected: u32,
+        found:    u32
+    },
+    /// The block index of a seekable stream is missing or does not match its blocks
+    InvalidIndex
+    {
+        block:  usize,
+        offset: usize,
+        reason: &'static str
+    },
+    /// The input goes on after the end of the stream
+    TrailingData
+    {
+        block: usize, offset: usize
+    }
+}
+
+impl GlzError
+{
+    /// Index of the block where the error occurred
+    pub fn block(&self) -> usize
+    {
+        match self
+        {
+            Self::Io { block, .. }
+            | Self::Truncated { block, .. }
+            | Self::InvalidOffset { block, .. }
+            | Self::LiteralOverflow { block, .. }
+            | Self::BlockTooLarge { block, .. }
+            | Self::OutputOverflow { block, .. }
+            | Self::InvalidHeader { block, .. }
+            | Self::ContentSizeMismatch { block, .. }
+            | Self::DictionaryMismatch { block, .. }
+            | Self::BlockChecksumMismatch { block, .. }
+            | Self::ContentChecksumMismatch { block, .. }
+            | Self::InvalidIndex { block, .. }
+            | Self::TrailingData { block, .. } => *block
+        }
+    }
+
+    /// Byte offset where the error occurred
+    pub fn offset(&self) -> usize
+    {
+        match self
+        {
+            Self::Io { offset, .. }
+            | Self::Truncated { offset, .. }
+            | Self::InvalidOffset { offset, .. }
+            | Self::LiteralOverflow { offset, .. }
+            | Self::BlockTooLarge { offset, .. }
+            | Self::OutputOverflow { offset, .. }
+            | Self::InvalidHeader { offset, .. }
+            | Self::ContentSizeMismatch { offset, .. }
+            | Self::DictionaryMismatch { offset, .. }
+            | Self::BlockChecksumMismatch { offset, .. }
+            | Self::ContentChecksumMismatch { offset, .. }
+            | Self::InvalidIndex { offset, .. }
+            | Self::TrailingData { offset, .. } => *offset
+        }
+    }
+
+    /// Place an error reported relative to the start of a block
+    /// at block `block_index` which starts at `block_start` in the stream
+    pub(crate) fn at_block(mut self, block_index: usize, block_start: usize) -> Self
+    {
+        match &mut self
+        {
+            Self::Io { block, offset, .. }
+            | Self::Truncated { block, offset }
+            | Self::InvalidOffset { block, offset, .. }
+            | Self::LiteralOverflow { block, offset, .. }
+            | Self::BlockTooLarge { block, offset, .. }
+            | Self::OutputOverflow { block, offset }
+            | Self::InvalidHeader { block, offset, .. }
+            | Self::ContentSizeMismatch { block, offset, .. }
+            | Self::DictionaryMismatch { block, offset, .. }
+            | Self::BlockChecksumMismatch { block, offset, .. }
+            | Self::ContentChecksumMismatch { block, offset, .. }
+            | Self::InvalidIndex { block, offset, .. }
+            | Self::TrailingData { block, offset } =>
+            {
+                *block = block_index;
+                *offset += block_start;
+            }
+        }
+        self
+    }
+}
+
+impl Display for GlzError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Io {
+                block,
+                offset,
+                error
+            } =>
+            {
+                write!(f, "I/O error in block {block} at offset {offset}: {error}")
+            }
+            Self::Truncated { block, offset } =>
+            {
+                write!(f, "Input truncated in block {block} at offset {offset}")
+            }
+            Self::InvalidOffset {
+                block,
+                offset,
+                distance,
+                position
+            } =>
+            {
+                write!(
+                    f,
+                    "Match distance {distance} goes past the start of the output (position {position}) in block {block} at offset {offset}"
+                )
+            }
+            Self::LiteralOverflow {
+                block,
+                offset,
+                length
+            } =>
+            {
+                write!(
+                    f,
+                    "Literal run of {length} bytes overflows the input in block {block} at offset {offset}"
+                )
+            }
+            Self::BlockTooLarge {
+                block,
+                offset,
+                size
+            } =>
+            {
+                write!(
+                    f,
+                    "Block {block} at offset {offset} has size {size}, larger than the maximum allowed"
+                )
+            }
+            Self::OutputOverflow { block, offset } =>
+            {
+                write!(
+                    f,
+                    "Output buffer overflow in block {block} at offset {offset}"
+                )
+            }
+            Self::InvalidHeader {
+                block,
+                offset,
+                reason
+            } =>
+            {
+                write!(
+                    f,
+                    "Invalid frame header, {reason}, in block {block} at offset {offset}"
+                )
+            }
+            Self::ContentSizeMismatch {
+                block,
+                offset,
+                expected,
+                found
+            } =>
+            {
+                write!(
+                    f,
+                    "Stream decoded to {found} bytes but its header records {expected}, in block {block} at offset {offset}"
+                )
+            }
+            Self::DictionaryMismatch {
+                block,
+                offset,
+                expected,
+                found
+            } => match (expected, found)
+            {
+                (Some(expected), None) => write!(
+                    f,
+                    "Stream needs dictionary {expected:08x} but none was given, in block {block} at offset {offset}"
+                ),
+                (Some(expected), Some(found)) => write!(
+                    f,
+                    "Dictionary {found:08x} given but the stream needs dictionary {expected:08x}, in block {block} at offset {offset}"
+                ),
+                (None, _) => write!(
+                    f,
+                    "Stream does not use a dictionary but one was given, in block {block} at offset {offset}"
+                )
+            },
+            Self::BlockChecksumMismatch {
+                block,
+                offset,
+                expected,
+                found
+            } =>
+            {
+                write!(
+                    f,
+                    "Block {block} at offset {offset} is corrupt, it decoded to checksum {found:08x} but {expected:08x} was recorded"
+                )
+            }
+            Self::ContentChecksumMismatch {
+                block,
+                offset,
+                expected,
+                found
+            } =>
+            {
+                write!(
+                    f,
+                    "Stream is corrupt, its content decoded to checksum {found:08x} but {expected:08x} was recorded, in block {block} at offset {offset}"
+                )
+            }
+            Self::InvalidIndex {
+                block,
+                offset,
+                reason
+            } =>
+            {
+                write!(
+                    f,
+                    "Invalid block index, {reason}, in block {block} at offset {offset}"
+                )
+            }
+            Self::TrailingData { block, offset } =>
+            {
+                write!(
+                    f,
+                    "Input goes on after the end of the stream, in block {block} at offset {offset}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GlzError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self
+        {
+            Self::Io { error, .. } => Some(error),
+            _ => None
+        }
+    }
+}
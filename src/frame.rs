@@ -0,0 +1,385 @@
+//! The optional frame header at the start of a stream.
+//!
+//! ```text
+//! magic (4) | version (1) | flags (1) | block size log2 (1)
+//!     | dictionary ID (4, optional) | content size (8, optional) | header checksum (1)
+//! ```
+//!
+//! The magic read as a block length prefix is larger than `MEM_SIZE`,
+//! so no stream without a header can start with it.
+//!
+//! With block checksums every block is followed by the xxHash32 of its decoded bytes.
+//! With a content checksum or a block index the last block is followed by an end mark,
+//! a zero length prefix, then the xxHash32 of the whole decoded content if flagged.
+//!
+//! A seekable stream has independent blocks and ends with an index of them,
+//! read from the end of the stream:
+//!
+//! ```text
+//! per block: compressed offset (8) | decompressed size (4)
+//!     | entry count (4) | index magic (4)
+//! ```
+
+use crate::checksum::xxh32;
+use crate::constants::MEM_SIZE;
+use crate::errors::GlzError;
+
+/// First bytes of a stream with a frame header
+pub const FRAME_MAGIC: [u8; 4] = *b"GLZ\x1b";
+/// Version of the format written by this crate
+pub const FRAME_VERSION: u8 = 1;
+/// Last bytes of a seekable stream, after its block index
+pub const INDEX_MAGIC: [u8; 4] = *b"GLZi";
+
+/// Every block is followed by a checksum of its decoded bytes
+const FLAG_BLOCK_CHECKSUMS: u8 = 1 << 0;
+/// The stream ends with a checksum of the decoded content
+const FLAG_CONTENT_CHECKSUM: u8 = 1 << 1;
+/// Blocks may reach into the blocks before them
+const FLAG_LINKED: u8 = 1 << 2;
+/// The header holds the ID of the dictionary the stream was compressed with
+const FLAG_DICTIONARY_ID: u8 = 1 << 3;
+/// The header holds the size of the decompressed content
+const FLAG_CONTENT_SIZE: u8 = 1 << 4;
+/// The stream ends with an index of its blocks
+const FLAG_SEEKABLE: u8 = 1 << 5;
+
+const KNOWN_FLAGS: u8 = FLAG_BLOCK_CHECKSUMS
+    | FLAG_CONTENT_CHECKSUM
+    | FLAG_LINKED
+    | FLAG_DICTIONARY_ID
+    | FLAG_CONTENT_SIZE
+    | FLAG_SEEKABLE;
+
+/// Prefix marking the end of the blocks, before the content checksum and block index
+pub(crate) const END_MARK: [u8; 4] = [0; 4];
+
+/// Size of the header up to and including the block size
+pub(crate) const FIXED_HEADER_SIZE: usize = 7;
+
+/// Size of an [`IndexEntry`] in the block index
+pub(crate) const INDEX_ENTRY_SIZE: usize = 12;
+/// Size of the entry count and magic closing the block index
+pub(crate) const INDEX_FOOTER_SIZE: usize = 8;
+
+/// Description of a stream, written before its first block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameHeader
+{
+    /// Largest number of bytes a block decodes to, a power of two
+    pub block_size:       usize,
+    /// Every block is followed by the xxHash32 of its decoded bytes
+    pub block_checksums:  bool,
+    /// The blocks are followed by an end mark and the xxHash32 of the decoded content
+    pub content_checksum: bool,
+    /// Blocks may reach into the blocks before them
+    pub linked:           bool,
+    /// [`dictionary_id`](crate::dictionary_id) of the dictionary the stream was compressed with
+    pub dictionary_id:    Option<u32>,
+    /// Size of the decompressed content, if it was known when compressing
+    pub content_size:     Option<u64>,
+    /// The stream ends with an index of its blocks, which are independent
+    pub seekable:         bool
+}
+
+/// A block of a seekable stream, as recorded in its index
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry
+{
+    /// Offset of the block's length prefix from the start of the stream
+    pub compressed_offset: u64,
+    /// Number of bytes the block decodes to
+    pub decompressed_size: u32
+}
+
+impl FrameHeader
+{
+    fn flags(&self) -> u8
+    {
+        let mut flags = 0;
+
+        if self.block_checksums
+        {
+            flags |= FLAG_BLOCK_CHECKSUMS;
+        }
+        if self.content_checksum
+        {
+            flags |= FLAG_CONTENT_CHECKSUM;
+        }
+        if self.linked
+        {
+            flags |= FLAG_LINKED;
+        }
+        if self.dictionary_id.is_some()
+        {
+            flags |= FLAG_DICTIONARY_ID;
+        }
+        if self.content_size.is_some()
+        {
+            flags |= FLAG_CONTENT_SIZE;
+        }
+        if self.seekable
+        {
+            flags |= FLAG_SEEKABLE;
+        }
+        flags
+    }
+
+    /// Whether the blocks are followed by an end mark
+    pub(crate) fn has_end_mark(&self) -> bool
+    {
+        self.content_checksum || self.seekable
+    }
+
+    /// Size in bytes of a header with `flags`
+    pub(crate) fn encoded_size(flags: u8) -> usize
+    {
+        let mut size = FIXED_HEADER_SIZE + 1;
+
+        if flags & FLAG_DICTIONARY_ID != 0
+        {
+            size += 4;
+        }
+        if flags & FLAG_CONTENT_SIZE != 0
+        {
+            size += 8;
+        }
+        size
+    }
+
+    /// Encode the header
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        debug_assert!(self.block_size.is_power_of_two());
+
+        let mut bytes = Vec::with_capacity(Self::encoded_size(self.flags()));
+
+        bytes.extend_from_slice(&FRAME_MAGIC);
+        bytes.push(FRAME_VERSION);
+        bytes.push(self.flags());
+        bytes.push(self.block_size.trailing_zeros() as u8);
+
+        if let Some(id) = self.dictionary_id
+        {
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        if let Some(size) = self.content_size
+        {
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes.push(header_checksum(&bytes));
+
+        bytes
+    }
+
+    /// Parse the header at the start of `input`, returning it with its size in bytes.
+    ///
+    /// Returns `None` if `input` does not start with [`FRAME_MAGIC`],
+    /// as a stream written without a header.
+    pub fn parse(input: &[u8]) -> Result<Option<(FrameHeader, usize)>, GlzError>
+    {
+        if !input.starts_with(&FRAME_MAGIC)
+        {
+            return Ok(None);
+        }
+        let invalid = |reason| GlzError::InvalidHeader {
+            block: 0,
+            offset: 0,
+            reason
+        };
+        let truncated = || GlzError::Truncated {
+            block:  0,
+            offset: input.len()
+        };
+        let fixed = input.get(..FIXED_HEADER_SIZE).ok_or_else(truncated)?;
+        let (version, flags, block_log) = (fixed[4], fixed[5], fixed[6]);
+
+        if version != FRAME_VERSION
+        {
+            return Err(invalid("unsupported format version"));
+        }
+        if flags & !KNOWN_FLAGS != 0
+        {
+            return Err(invalid("unknown flags"));
+        }
+        let size = Self::encoded_size(flags);
+        let bytes = input.get(..size).ok_or_else(truncated)?;
+
+        if header_checksum(&bytes[..size - 1]) != bytes[size - 1]
+        {
+            return Err(invalid("header checksum mismatch"));
+        }
+        if 1_usize.checked_shl(u32::from(block_log)).is_none_or(|s| s > MEM_SIZE)
+        {
+            return Err(invalid("block size too large"));
+        }
+        let mut fields = &bytes[FIXED_HEADER_SIZE..size - 1];
+        let mut take = |length: usize| {
+            let (field, rest) = fields.split_at(length);
+            fields = rest;
+            field
+        };
+        let dictionary_id = (flags & FLAG_DICTIONARY_ID != 0)
+            .then(|| u32::from_le_bytes(take(4).try_into().unwrap()));
+        let content_size = (flags & FLAG_CONTENT_SIZE != 0)
+            .then(|| u64::from_le_bytes(take(8).try_into().unwrap()));
+
+        let header = FrameHeader {
+            block_size: 1 << block_log,
+            block_checksums: flags & FLAG_BLOCK_CHECKSUMS != 0,
+            content_checksum: flags & FLAG_CONTENT_CHECKSUM != 0,
+            linked: flags & FLAG_LINKED != 0,
+            dictionary_id,
+            content_size,
+            seekable: flags & FLAG_SEEKABLE != 0
+        };
+        Ok(Some((header, size)))
+    }
+
+    /// Check a stream of `blocks` blocks and `length` bytes, which decoded
+    /// to `found` bytes, against the recorded content size
+    pub(crate) fn check_content_size(
+        &self, found: u64, blocks: usize, length: usize
+    ) -> Result<(), GlzError>
+    {
+        match self.content_size
+        {
+            Some(expected) if expected != found => Err(GlzError::ContentSizeMismatch {
+                block: blocks,
+                offset: length,
+                expected,
+                found
+            }),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Append the block index of a seekable stream to `out`
+pub(crate) fn write_index(entries: &[IndexEntry], out: &mut Vec<u8>)
+{
+    for entry in entries
+    {
+        out.extend_from_slice(&entry.compressed_offset.to_le_bytes());
+        out.extend_from_slice(&entry.decompressed_size.to_le_bytes());
+    }
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    out.extend_from_slice(&INDEX_MAGIC);
+}
+
+/// Number of entries in the block index closed by `footer`, the last bytes of a stream
+pub(crate) fn parse_index_footer(footer: &[u8; INDEX_FOOTER_SIZE]) -> Result<usize, GlzError>
+{
+    if footer[4..] != INDEX_MAGIC
+    {
+        return Err(GlzError::InvalidIndex {
+            block:  0,
+            offset: 0,
+            reason: "missing index magic"
+        });
+    }
+    Ok(u32::from_le_bytes(footer[..4].try_into().unwrap()) as usize)
+}
+
+/// Parse the entries of a block index, without its footer
+pub(crate) fn parse_index(bytes: &[u8]) -> Vec<IndexEntry>
+{
+    bytes
+        .chunks_exact(INDEX_ENTRY_SIZE)
+        .map(|entry| IndexEntry {
+            compressed_offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+            decompressed_size: u32::from_le_bytes(entry[8..].try_into().unwrap())
+        })
+        .collect()
+}
+
+/// Read the checksum at `input[position..]`, following block `block`
+pub(crate) fn read_checksum(input: &[u8], position: usize, block: usize) -> Result<u32, GlzError>
+{
+    let bytes = input
+        .get(position..position + 4)
+        .ok_or(GlzError::Truncated {
+            block,
+            offset: input.len()
+        })?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Check block `block`, which decoded to `decoded`, against the checksum
+/// `expected` recorded at `offset`
+pub(crate) fn check_block_checksum(
+    decoded: &[u8], expected: u32, block: usize, offset: usize
+) -> Result<(), GlzError>
+{
+    let found = xxh32(decoded, 0);
+
+    if found != expected
+    {
+        return Err(GlzError::BlockChecksumMismatch {
+            block,
+            offset,
+            expected,
+            found
+        });
+    }
+    Ok(())
+}
+
+/// Check the checksum `found` of a stream's content against the `expected` one
+/// recorded at `offset`, after `blocks` blocks
+pub(crate) fn check_content_checksum(
+    found: u32, expected: u32, blocks: usize, offset: usize
+) -> Result<(), GlzError>
+{
+    if found != expected
+    {
+        return Err(GlzError::ContentChecksumMismatch {
+            block: blocks,
+            offset,
+            expected,
+            found
+        });
+    }
+    Ok(())
+}
+
+/// Second byte of the xxHash32 of the header, as in the LZ4 frame format
+fn header_checksum(header: &[u8]) -> u8
+{
+    (xxh32(&header[FRAME_MAGIC.len()..], 0) >> 8) as u8
+}
+
+#[test]
+fn frame_header_round_trip()
+{
+    use crate::constants::BLOCK_SIZE;
+
+    for (dictionary_id, content_size) in [(None, None), (Some(7), None), (Some(9), Some(1 << 40))]
+    {
+        let header = FrameHeader {
+            block_size: BLOCK_SIZE,
+            block_checksums: content_size.is_some(),
+            content_checksum: dictionary_id.is_some(),
+            linked: dictionary_id.is_some(),
+            dictionary_id,
+            content_size,
+            seekable: content_size.is_none()
+        };
+        let mut bytes = header.to_bytes();
+        bytes.extend_from_slice(b"blocks");
+
+        assert_eq!(
+            FrameHeader::parse(&bytes).unwrap(),
+            Some((header, bytes.len() - 6))
+        );
+
+        bytes[6] ^= 1;
+        assert!(matches!(
+            FrameHeader::parse(&bytes),
+            Err(GlzError::InvalidHeader { .. })
+        ));
+    }
+    assert_eq!(FrameHeader::parse(b"\x10\x00\x00\x00").unwrap(), None);
+    // the magic can not be the prefix of a block
+    assert!(crate::utils::BlockPrefix::from_bytes(FRAME_MAGIC).size > MEM_SIZE);
+}
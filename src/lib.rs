@@ -0,0 +1,36 @@
+//! GLZ, a small LZ77 style compressor.
+//!
+//! A compressed stream is a series of blocks, each prefixed by its
+//! compressed length as a 4 byte little endian integer, see the README
+//! for the layout of a block.
+//!
+//! # Example
+//! ```
+//! let data = b"hello hello hello hello hello hello hello".repeat(10);
+//!
+//! let compressed = glz::compress_to_vec(&data);
+//! let decompressed = glz::decompress_to_vec(&compressed).unwrap();
+//!
+//! assert_eq!(data, decompressed);
+//! ```
+#![allow(clippy::needless_return)]
+
+pub use crate::checksum::dictionary_id;
+pub use crate::compress::{
+    compress_to_vec, compress_to_vec_with, compress_with_dict, train_dictionary, CompressOptions,
+    Compressor, GlzWriter
+};
+pub use crate::decompress::{
+    decompress_to_vec, decompress_to_vec_parallel, decompress_to_vec_trusted, decompress_with_dict,
+    list_blocks, verify, GlzReader, SeekableReader
+};
+pub use crate::errors::GlzError;
+pub use crate::frame::{FrameHeader, IndexEntry};
+
+mod checksum;
+pub mod compress;
+pub mod constants;
+pub mod decompress;
+mod errors;
+pub mod frame;
+mod utils;
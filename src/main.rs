@@ -1,28 +1,320 @@
-#![feature(slice_split_at_unchecked)]
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Instant;
 
-use crate::compress::compress;
-use crate::decompress::decompress;
+use glz::compress::{
+    compress_stream, train_dictionary, CompressOptions, Compressor, DEFAULT_LEVEL, MAX_LEVEL,
+    MIN_LEVEL
+};
+use glz::decompress::{
+    decompress_file, decompress_file_trusted, decompress_stream, list_blocks, verify,
+    verify_file, StreamInfo
+};
+use glz::GlzError;
 
-mod compress;
-mod constants;
-mod decompress;
-mod utils;
+use crate::batch::{
+    compressed_name, decompressed_name, run_batch, take_paths, Paths
+};
+use crate::bench::{parse_depths, parse_levels, parse_strategies, BenchOptions};
+use crate::dump::DumpOptions;
+
+mod batch;
+mod bench;
+mod dump;
 
 const HELP_MESSAGE: &str = "
 USAGE
-  glz [OPTIONS] <input_file> <output_file>
+  glz c|d [OPTIONS] <files_and_directories>...
+  glz c|d [OPTIONS] <input_file> <output_file>
+  glz c|d [OPTIONS] -c <input_file>
+  glz c|d [OPTIONS] <input_file> -o <output_file>
+  glz t [-T<n>] <compressed_files>...
+  glz l <compressed_files>...
+  glz dump [--block <n>] [--preview <bytes>] [--json] <compressed_file>
+  glz b [-l <min>..<max>] [--strategy <list>] [--depth <list>] [-i <n>] [-T<n>] [--checked]
+        [--json] <files>...
+  glz train [--size <bytes>] <sample_files>... -o <dictionary>
+
+  An input or output file of - is stdin or stdout, as in `tar c dir | glz c - - | ssh ...`
 
 OPTIONS
-    d Decompress input file into output file
+    c     Compress files, each into <file>.glz next to it, or input file into output file
+    d     Decompress files, each <file>.glz into <file> next to it,
+          or input file into output file
+    t     Test compressed files, decoding every block without writing anything.
+          Exits with an error naming the first bad block of any damaged file
+    l     List the blocks of compressed files with their offsets, sizes, ratios and
+          number of sequences, without decompressing them
+    dump  Print every sequence of a compressed file as it was encoded: its literal length,
+          the first literals, match offset, match length and size in bytes
+    b     Benchmark compression and decompression of files in memory, reporting the ratio,
+          speed and peak memory at every level and strategy given
+    train Build a dictionary from sample files, for compressing small inputs like them
+
+COMMON OPTIONS
+    -c, --stdout     Write to stdout, the output file is not given
+    -o, --output <file>
+                     Write the one input file given to <file>, replacing it if it exists
+    -r, --recursive  Also take the files in subdirectories of the directories given
+    -f, --force      Replace the files next to the inputs that already exist
+
+COMPRESSION OPTIONS
+    -1 .. -12 Compression level, -1 is the fastest, -12 the strongest (default -6)
+    --linked  Let blocks match data in the blocks before them, improves the ratio
+              but blocks can only be decoded in order
+    -T<n>     Compress on <n> threads, at most one per core and per block of the input,
+              -T0 uses every core (default 1). The output is the same for any number
+              of threads
+    --no-header
+              Leave out the frame header, for tools reading the original headerless format.
+              Implies --no-checksums
+    --no-checksums
+              Leave out the checksums of every block and of the whole input,
+              which let decompression detect corrupt data
+    --seekable
+              End the output with an index of its blocks, so any part of it can be
+              decompressed on its own. Blocks are not linked
+
+DECOMPRESSION AND TEST OPTIONS
+    -T<n>     Decode up to <n> blocks at once, -T0 uses every core (default 1).
+              Streams of linked blocks are decoded on one thread
+    --checked Decompress with the bounds checked decoder, for files from untrusted sources.
+              It is slower, t always uses it
+
+BENCHMARK OPTIONS
+    -l <level>, -l <min>..<max>
+                Levels to run, such as 1..12 (default the level given by -1 .. -12)
+    --strategy <list>
+                Parsers to run at every level instead of the level's own, a comma separated
+                list of greedy, lazy, lazy2 and optimal. Optimal uses the binary tree
+                match finder, the others hash chains
+    --depth <list>
+                Match finder search depths to run every parser at instead of the level's own,
+                a comma separated list such as 8,20,64
+    -i <n>      Times every setting is run, the fastest is reported (default 3)
+    -T<n>       Compress and decompress on <n> threads (default 1)
+    --checked   Decompress with the bounds checked decoder instead of the fast one
+    --json      Print the results as JSON instead of a table
+
+    The block size and window size are fixed when glz is built, they are printed with
+    the results
+
+DUMP OPTIONS
+    --block <n>       Only print the sequences of block <n>
+    --preview <bytes> Literal bytes shown for every sequence (default 16)
+    --json            Print one JSON object per sequence, stored block and dictionary record
+                      instead of a table
+
+TRAINING OPTIONS
+    -o, --output <file> Where to write the dictionary
+    --size <bytes>      Largest dictionary size (default 65536)
 
 ARGS:
-    <input_directory> is the path to the directory with *.jpg files to be compressed (no nested subdirectories).
-    <compressed_file> is the path to the archive file where the recompressed data of all input files is stored.
+    <files_and_directories> are compressed or decompressed one by one. A directory stands
+        for the files in it, those ending in .glz for d and the others for c
+    <input_file> <output_file> are one input and its output, replaced if it exists, unless
+        -r is given, either is a directory, or both are files that would be compressed
+        or decompressed each into its own, such as a.glz b.glz for d. Use -o to write
+        into such a file
 ";
 
+/// Dictionary size `glz train` aims for, offsets into it fit in a 2 byte `encode_mod`
+const DEFAULT_DICTIONARY_SIZE: usize = 1 << 16;
+
+/// Remove a `-1` to `-12` level flag from `args`,
+/// returning the default level if none was given.
+///
+/// pico-args only accepts single character short flags, so these are handled by hand.
+fn take_level(args: &mut Vec<OsString>) -> u8
+{
+    let mut level = DEFAULT_LEVEL;
+
+    args.retain(|arg| {
+        let parsed = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix('-'))
+            .and_then(|arg| arg.parse::<u8>().ok())
+            .filter(|l| (MIN_LEVEL..=MAX_LEVEL).contains(l));
+
+        if let Some(l) = parsed
+        {
+            level = l;
+        }
+        parsed.is_none()
+    });
+    level
+}
+
+/// Remove a `-T<n>` thread count flag from `args`, returning one thread if none was given
+/// and every available core for `-T0`.
+fn take_threads(args: &mut Vec<OsString>) -> usize
+{
+    let mut threads = 1;
+
+    args.retain(|arg| {
+        let parsed = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix("-T"))
+            .and_then(|arg| arg.parse::<usize>().ok());
+
+        if let Some(t) = parsed
+        {
+            threads = t;
+        }
+        parsed.is_none()
+    });
+    if threads == 0
+    {
+        threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    }
+    threads
+}
+
+/// Open `path` for writing, `-` being stdout
+fn open_output(path: &str) -> Box<dyn Write>
+{
+    if path == "-"
+    {
+        return Box::new(io::stdout().lock());
+    }
+    match File::create(path)
+    {
+        Ok(file) => Box::new(file),
+        Err(e) => exit_with(format!("{path}: {e}"))
+    }
+}
+
+/// Read all of `path`, `-` being stdin
+fn read_input(path: impl AsRef<std::path::Path>) -> io::Result<Vec<u8>>
+{
+    if path.as_ref() == std::path::Path::new("-")
+    {
+        let mut input = Vec::new();
+
+        io::stdin().lock().read_to_end(&mut input)?;
+        return Ok(input);
+    }
+    std::fs::read(path)
+}
+
+/// Print the frame header and blocks of the `size` byte stream `info`, then their totals
+fn print_stream_info(info: &StreamInfo, size: usize)
+{
+    match info.header
+    {
+        Some(header) =>
+        {
+            let mut flags = vec![format!("block size {}", header.block_size)];
+
+            let named = [
+                (header.block_checksums, "block checksums"),
+                (header.content_checksum, "content checksum"),
+                (header.linked, "linked"),
+                (header.seekable, "seekable")
+            ];
+            flags.extend(named.iter().filter(|(set, _)| *set).map(|(_, name)| name.to_string()));
+
+            if let Some(id) = header.dictionary_id
+            {
+                flags.push(format!("dictionary {id:08x}"));
+            }
+            if let Some(content_size) = header.content_size
+            {
+                flags.push(format!("content size {content_size}"));
+            }
+            println!("  frame header: {}", flags.join(", "));
+        }
+        None => println!("  no frame header")
+    }
+    println!(
+        "  {:>6} {:>12} {:>12} {:>12} {:>7} {:>9}  kind",
+        "block", "offset", "compressed", "decompressed", "ratio", "sequences"
+    );
+    for (i, block) in info.blocks.iter().enumerate()
+    {
+        let kind = if block.dictionary
+        {
+            "dictionary"
+        }
+        else if block.stored
+        {
+            "stored"
+        }
+        else if block.linked
+        {
+            "linked"
+        }
+        else
+        {
+            ""
+        };
+        let line = format!(
+            "  {:>6} {:>12} {:>12} {:>12} {:>7.3} {:>9}  {}",
+            i,
+            block.offset,
+            block.compressed_size,
+            block.decompressed_size,
+            block.decompressed_size as f64 / block.compressed_size.max(1) as f64,
+            block.sequences,
+            kind
+        );
+        println!("{}", line.trim_end());
+    }
+    let decompressed = info.decompressed_size();
+    let sequences: usize = info.blocks.iter().map(|block| block.sequences).sum();
+
+    println!(
+        "  {} blocks, {} sequences, {size} -> {decompressed} bytes, ratio {:.3}",
+        info.blocks.len(),
+        sequences,
+        decompressed as f64 / size.max(1) as f64
+    );
+    if size > info.end
+    {
+        println!("  {} byte block index after the blocks", size - info.end);
+    }
+}
+
+/// Print `error` and exit with a failure status
+fn exit_with(error: impl std::fmt::Display) -> !
+{
+    eprintln!("{error}");
+    std::process::exit(1);
+}
+
+/// Decompress `input` into `output` on `threads` threads, with the checked decoder
+/// if `checked` and otherwise with the fast one
+fn decompress_local<W: Write>(
+    input: &Path, output: W, threads: usize, checked: bool
+) -> Result<(u64, u64), GlzError>
+{
+    if checked
+    {
+        return decompress_file(input, output, threads);
+    }
+    // SAFETY: `glz d` trusts the files it is given unless told otherwise with --checked,
+    // as it did before there was a checked decoder
+    unsafe { decompress_file_trusted(input, output, threads) }
+}
+
+/// The USAGE section of the help message
+fn usage() -> &'static str
+{
+    let start = HELP_MESSAGE.find("USAGE").unwrap();
+    let end = HELP_MESSAGE[start..].find("\n\n").map_or(HELP_MESSAGE.len(), |end| start + end);
+
+    &HELP_MESSAGE[start..end]
+}
+
 fn main()
 {
-    let mut pargs = pico_args::Arguments::from_env();
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let level = take_level(&mut args);
+    let threads = take_threads(&mut args);
+
+    let mut pargs = pico_args::Arguments::from_vec(args);
 
     if pargs.contains(["-h", "--help"])
     {
@@ -34,19 +326,313 @@ fn main()
         if sub == "d"
         {
             // decompression code
-            let in_file: String = pargs.free_from_str().expect("Input file not given");
-            let out_file: String = pargs.free_from_str().expect("Output  file not given");
-            decompress(in_file, out_file);
+            let stdout = pargs.contains(["-c", "--stdout"]);
+            let recursive = pargs.contains(["-r", "--recursive"]);
+            let force = pargs.contains(["-f", "--force"]);
+            let checked = pargs.contains("--checked");
+            let output = pargs
+                .opt_value_from_str(["-o", "--output"])
+                .unwrap_or_else(|e| exit_with(e));
+
+            let (in_file, out_file) =
+                match take_paths(pargs, stdout, recursive, output, decompressed_name)
+            {
+                Paths::Single(input, output) => (input, output),
+                Paths::Batch(paths) =>
+                {
+                    let done = run_batch(
+                        &paths,
+                        recursive,
+                        force,
+                        "Decompressed",
+                        decompressed_name,
+                        |input, output| decompress_local(input, output, threads, checked)
+                    );
+                    std::process::exit(if done { 0 } else { 1 });
+                }
+            };
+            let start = Instant::now();
+            let output = open_output(&out_file);
+
+            // pipes can not be mapped, they are read a block at a time
+            let result = if in_file == "-"
+            {
+                decompress_stream(io::stdin().lock(), output)
+            }
+            else
+            {
+                decompress_local(in_file.as_ref(), output, threads, checked)
+            };
+            match result
+            {
+                Ok((read, written)) => eprintln!("{read}->{written} in {:?}", start.elapsed()),
+                Err(e) => exit_with(e)
+            }
+        }
+        else if sub == "t"
+        {
+            let files = pargs.finish();
+            let total = files.len();
+            let mut failed = 0;
+
+            if files.is_empty()
+            {
+                exit_with("No compressed files given");
+            }
+            for file in files
+            {
+                let name = file.to_string_lossy();
+
+                let result = if file == "-"
+                {
+                    let mut input = Vec::new();
+
+                    io::stdin()
+                        .lock()
+                        .read_to_end(&mut input)
+                        .map_err(|error| GlzError::Io {
+                            block: 0,
+                            offset: input.len(),
+                            error
+                        })
+                        .and_then(|_| verify(&input, threads))
+                }
+                else
+                {
+                    verify_file(&file, threads)
+                };
+                match result
+                {
+                    Ok((blocks, size)) => println!("{name}: OK, {blocks} blocks, {size} bytes"),
+                    Err(e) =>
+                    {
+                        eprintln!("{name}: {e}");
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0
+            {
+                exit_with(format!("{failed} of {total} files failed the test"));
+            }
+        }
+        else if sub == "l"
+        {
+            let files = pargs.finish();
+            let total = files.len();
+            let mut failed = 0;
+
+            if files.is_empty()
+            {
+                exit_with("No compressed files given");
+            }
+            for file in files
+            {
+                let name = file.to_string_lossy();
+
+                let info = read_input(&file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|input| {
+                        let info = list_blocks(&input).map_err(|e| e.to_string())?;
+                        Ok((info, input.len()))
+                    });
+
+                match info
+                {
+                    Ok((info, size)) =>
+                    {
+                        println!("{name}:");
+                        print_stream_info(&info, size);
+                    }
+                    Err(e) =>
+                    {
+                        eprintln!("{name}: {e}");
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0
+            {
+                exit_with(format!("{failed} of {total} files could not be listed"));
+            }
+        }
+        else if sub == "dump"
+        {
+            let options = DumpOptions {
+                block:   pargs
+                    .opt_value_from_str("--block")
+                    .unwrap_or_else(|e| exit_with(e)),
+                preview: pargs
+                    .opt_value_from_str("--preview")
+                    .unwrap_or_else(|e| exit_with(e))
+                    .unwrap_or(16),
+                json:    pargs.contains("--json")
+            };
+            let file: String = pargs
+                .free_from_str()
+                .unwrap_or_else(|_| exit_with("Compressed file not given"));
+
+            let input = read_input(&file).unwrap_or_else(|e| exit_with(format!("{file}: {e}")));
+
+            if let Err(e) = dump::run(&input, &options)
+            {
+                exit_with(format!("{file}: {e}"));
+            }
+        }
+        else if sub == "b"
+        {
+            let levels = pargs
+                .opt_value_from_fn("-l", parse_levels)
+                .unwrap_or_else(|e| exit_with(e))
+                .unwrap_or(level..=level);
+            let strategies = pargs
+                .opt_value_from_fn("--strategy", parse_strategies)
+                .unwrap_or_else(|e| exit_with(e))
+                .map_or(vec![None], |strategies| strategies.into_iter().map(Some).collect());
+            let depths = pargs
+                .opt_value_from_fn("--depth", parse_depths)
+                .unwrap_or_else(|e| exit_with(e))
+                .map_or(vec![None], |depths| depths.into_iter().map(Some).collect());
+            let iterations = pargs
+                .opt_value_from_str("-i")
+                .unwrap_or_else(|e| exit_with(e))
+                .unwrap_or(3);
+            let checked = pargs.contains("--checked");
+            let json = pargs.contains("--json");
+
+            let files: Vec<(String, Vec<u8>)> = pargs
+                .finish()
+                .iter()
+                .map(|path| {
+                    let name = path.to_string_lossy().into_owned();
+                    let data = std::fs::read(path)
+                        .unwrap_or_else(|e| exit_with(format!("{name}: {e}")));
+                    (name, data)
+                })
+                .collect();
+
+            if files.is_empty()
+            {
+                exit_with("No files given");
+            }
+            let options = BenchOptions {
+                levels,
+                strategies,
+                depths,
+                iterations,
+                threads,
+                checked,
+                json
+            };
+            if let Err(e) = bench::run(&files, &options)
+            {
+                exit_with(e);
+            }
+        }
+        else if sub == "train"
+        {
+            let output: String = pargs
+                .value_from_str(["-o", "--output"])
+                .unwrap_or_else(|e| exit_with(e));
+            let size = pargs
+                .opt_value_from_str("--size")
+                .unwrap_or_else(|e| exit_with(e))
+                .unwrap_or(DEFAULT_DICTIONARY_SIZE);
+            let samples: Vec<Vec<u8>> = pargs
+                .finish()
+                .iter()
+                .map(|path| {
+                    std::fs::read(path).unwrap_or_else(|e| {
+                        exit_with(format!("{}: {e}", path.to_string_lossy()))
+                    })
+                })
+                .collect();
+
+            if samples.is_empty()
+            {
+                exit_with("No sample files given");
+            }
+            let dictionary = train_dictionary(&samples, size);
+
+            if let Err(e) = std::fs::write(&output, &dictionary)
+            {
+                exit_with(format!("{output}: {e}"));
+            }
+            println!(
+                "Trained a {} byte dictionary from {} samples",
+                dictionary.len(),
+                samples.len()
+            );
         }
         else if sub == "c"
         {
-            let in_file: String = pargs.free_from_str().expect("Input file not given");
-            let out_file: String = pargs.free_from_str().expect("Output  file not given");
-            compress(in_file, out_file);
+            let header = !pargs.contains("--no-header");
+            // checksums are announced by the header
+            let checksums = header && !pargs.contains("--no-checksums");
+
+            let options = CompressOptions {
+                level,
+                linked: pargs.contains("--linked"),
+                threads,
+                header,
+                block_checksums: checksums,
+                content_checksum: checksums,
+                seekable: pargs.contains("--seekable"),
+                ..Default::default()
+            };
+            let stdout = pargs.contains(["-c", "--stdout"]);
+            let recursive = pargs.contains(["-r", "--recursive"]);
+            let force = pargs.contains(["-f", "--force"]);
+            let output = pargs
+                .opt_value_from_str(["-o", "--output"])
+                .unwrap_or_else(|e| exit_with(e));
+            // one compressor for every file, its match finders are large to set up
+            let mut compressor = Compressor::new(&options);
+
+            let (in_file, out_file) =
+                match take_paths(pargs, stdout, recursive, output, compressed_name)
+            {
+                Paths::Single(input, output) => (input, output),
+                Paths::Batch(paths) =>
+                {
+                    let done = run_batch(
+                        &paths,
+                        recursive,
+                        force,
+                        "Compressed",
+                        compressed_name,
+                        |input, output| compressor.compress_file(input, output)
+                    );
+                    std::process::exit(if done { 0 } else { 1 });
+                }
+            };
+            let start = Instant::now();
+            let output = open_output(&out_file);
+
+            let result = if in_file == "-"
+            {
+                compress_stream(io::stdin().lock(), output, &options, None)
+            }
+            else
+            {
+                compressor.compress_file(&in_file, output)
+            };
+            match result
+            {
+                Ok((read, written)) =>
+                {
+                    eprintln!("Compressed {read} to {written} in {:?}", start.elapsed());
+                }
+                Err(e) => exit_with(e)
+            }
+        }
+        else
+        {
+            exit_with(format!("Unknown subcommand {sub}, see glz --help"));
         }
     }
     else
     {
-        print!("No arguments passed quiting");
+        exit_with(format!("No subcommand given, see glz --help\n\n{}", usage()));
     }
 }
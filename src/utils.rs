@@ -1,5 +1,61 @@
+// the encoding helpers keep the form they were written in
+#![allow(clippy::assign_op_pattern, clippy::non_minimal_cfg)]
+
 use crate::compress::EncodeSequence;
-use crate::constants::{GLZ_MIN_MATCH, LITERAL_BITS, ML_BITS, OFFSET_BIT, TOKEN};
+use crate::constants::{
+    BLOCK_DICTIONARY, BLOCK_LINKED, BLOCK_SIZE_MASK, BLOCK_STORED, GLZ_MIN_MATCH, LITERAL_BITS,
+    ML_BITS, OFFSET_BIT, TOKEN
+};
+
+/// The 4 byte little endian prefix before every block,
+/// its compressed length and flags in the top bits
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockPrefix
+{
+    pub size:       usize,
+    /// Matches may reach into the blocks before this one
+    pub linked:     bool,
+    /// This is a dictionary record rather than a block
+    pub dictionary: bool,
+    /// The block holds its bytes uncompressed
+    pub stored:     bool
+}
+
+impl BlockPrefix
+{
+    pub fn from_bytes(bytes: [u8; 4]) -> BlockPrefix
+    {
+        let prefix = u32::from_le_bytes(bytes);
+
+        BlockPrefix {
+            size:       (prefix & BLOCK_SIZE_MASK) as usize,
+            linked:     prefix & BLOCK_LINKED != 0,
+            dictionary: prefix & BLOCK_DICTIONARY != 0,
+            stored:     prefix & BLOCK_STORED != 0
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 4]
+    {
+        debug_assert!(self.size <= BLOCK_SIZE_MASK as usize);
+
+        let mut prefix = self.size as u32;
+
+        if self.linked
+        {
+            prefix |= BLOCK_LINKED;
+        }
+        if self.dictionary
+        {
+            prefix |= BLOCK_DICTIONARY;
+        }
+        if self.stored
+        {
+            prefix |= BLOCK_STORED;
+        }
+        prefix.to_le_bytes()
+    }
+}
 
 #[allow(unreachable_code)]
 pub fn count(window: &[u8], match_window: &[u8]) -> usize
@@ -224,7 +280,44 @@ pub fn write_token(seq: &EncodeSequence) -> u8
     out
 }
 
-fn compress_encode_mod(mut value: usize, dest: &mut [u8], dest_position: &mut usize)
+/// Number of bytes `compress_encode_mod` writes for `value`
+#[inline(always)]
+pub fn encode_mod_len(mut value: usize) -> usize
+{
+    let mut length = 1;
+
+    while value > 0x7f
+    {
+        value = (value - 0x80) >> 7;
+        length += 1;
+    }
+    length
+}
+
+/// Encoded size of `seq` in bytes, the token, `encode_mod` values and raw literals
+#[inline(always)]
+pub fn sequence_cost<const IS_END: bool>(seq: &EncodeSequence) -> usize
+{
+    let mut cost = 1 + seq.ll;
+
+    if seq.ll >= TOKEN
+    {
+        cost += encode_mod_len(seq.ll - TOKEN);
+    }
+    if IS_END
+    {
+        return cost;
+    }
+    cost += encode_mod_len(seq.ol >> 2);
+
+    if seq.ml >= TOKEN + GLZ_MIN_MATCH
+    {
+        cost += encode_mod_len(seq.ml - TOKEN - GLZ_MIN_MATCH);
+    }
+    cost
+}
+
+pub(crate) fn compress_encode_mod(mut value: usize, dest: &mut [u8], dest_position: &mut usize)
 {
     let mut left: i32;
 
@@ -316,6 +409,13 @@ pub unsafe fn v_hash<const MIN_LENGTH: u8>(h_ptr: *const u8, num_bits: usize) ->
 
     match MIN_LENGTH
     {
+        3 =>
+        {
+            const PRIME_BYTES: u32 = 506832829;
+            // drop the fourth byte
+            ((h_ptr.cast::<u32>().read_unaligned().to_le() << 8).wrapping_mul(PRIME_BYTES)
+                >> (32 - num_bits)) as usize
+        }
         4 =>
         {
             const PRIME_BYTES: u32 = 2654435761;
@@ -342,3 +442,57 @@ pub unsafe fn v_hash<const MIN_LENGTH: u8>(h_ptr: *const u8, num_bits: usize) ->
         }
     }
 }
+
+/// `len` bytes of xorshift32 output started from `seed`, data that does not compress,
+/// shared by the tests
+#[cfg(test)]
+pub(crate) fn pseudo_random(len: usize, mut seed: u32) -> Vec<u8>
+{
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        })
+        .collect()
+}
+
+/// About `len` bytes of sentences of common words, each said again and again with
+/// a word changed now and then, the long and slightly shifted repeats of text.
+/// Shared by the tests
+#[cfg(test)]
+pub(crate) fn pseudo_text(len: usize, seed: u32) -> Vec<u8>
+{
+    const WORDS: [&str; 50] = [
+        "the", "of", "and", "a", "to", "in", "is", "you", "that", "it", "he", "was", "for", "on",
+        "are", "as", "with", "his", "they", "at", "be", "this", "have", "from", "or", "one",
+        "had", "by", "word", "but", "not", "what", "all", "were", "we", "when", "your", "can",
+        "said", "there", "use", "an", "each", "which", "she", "do", "how", "their", "if", "will"
+    ];
+    let mut random = pseudo_random(len, seed).into_iter().cycle().map(usize::from);
+    let mut next = || random.next().unwrap();
+
+    let sentences: Vec<Vec<usize>> = (0..64)
+        .map(|_| (0..6 + next() % 10).map(|_| next() % WORDS.len()).collect())
+        .collect();
+    let mut text = Vec::with_capacity(len + 100);
+
+    while text.len() < len
+    {
+        let mut sentence = sentences[next() % sentences.len()].clone();
+        let changed = next() % sentence.len();
+
+        if next() % 2 == 0
+        {
+            sentence[changed] = next() % WORDS.len();
+        }
+        for word in sentence
+        {
+            text.extend_from_slice(WORDS[word].as_bytes());
+            text.push(b' ');
+        }
+        text.extend_from_slice(b". ");
+    }
+    text
+}